@@ -0,0 +1,95 @@
+//! Generates the default metadata of `new_default_meta` from `metadata.json` and `svgLogo.svg`.
+//!
+//! The name, symbol, decimals and reference come from `metadata.json`, the reference hash is the
+//! sha256 of `metadata.json` itself and the icon is `svgLogo.svg` encoded as a data URI, so
+//! updating either file regenerates a consistent default.
+use std::convert::TryFrom;
+use std::env;
+use std::fs;
+use std::path::Path;
+
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+const METADATA_PATH: &str = "metadata.json";
+const LOGO_PATH: &str = "svgLogo.svg";
+
+/// Removes the XML prolog (declaration, doctype and comments) before the root element.
+fn strip_prolog(svg: &str) -> &str {
+    let mut rest = svg.trim_start();
+    while rest.starts_with("<?") || rest.starts_with("<!") {
+        let end = rest
+            .find('>')
+            .unwrap_or_else(|| panic!("{}: unterminated prolog", LOGO_PATH));
+        rest = rest[end + 1..].trim_start();
+    }
+    rest
+}
+
+/// Encodes an SVG document as a `data:image/svg+xml` URI, percent-encoding only the characters
+/// that need it so the icon stays compact.
+fn svg_data_uri(svg: &str) -> String {
+    let collapsed = strip_prolog(svg)
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .replace("> <", "><")
+        .replace('"', "'");
+    let mut uri = String::from("data:image/svg+xml,");
+    for c in collapsed.chars() {
+        match c {
+            '%' => uri.push_str("%25"),
+            '#' => uri.push_str("%23"),
+            '<' => uri.push_str("%3C"),
+            '>' => uri.push_str("%3E"),
+            c => uri.push(c),
+        }
+    }
+    uri
+}
+
+fn string_field<'a>(metadata: &'a Value, field: &str) -> &'a str {
+    metadata[field]
+        .as_str()
+        .unwrap_or_else(|| panic!("{}: missing string field `{}`", METADATA_PATH, field))
+}
+
+fn main() {
+    println!("cargo:rerun-if-changed={}", METADATA_PATH);
+    println!("cargo:rerun-if-changed={}", LOGO_PATH);
+
+    let raw = fs::read(METADATA_PATH).expect("failed to read metadata.json");
+    let metadata: Value = serde_json::from_slice(&raw).expect("failed to parse metadata.json");
+    let svg = fs::read_to_string(LOGO_PATH).expect("failed to read svgLogo.svg");
+
+    let icon = svg_data_uri(&svg);
+    if metadata["icon"].as_str() != Some(icon.as_str()) {
+        println!(
+            "cargo:warning={} icon differs from {}; the default metadata uses {}",
+            METADATA_PATH, LOGO_PATH, LOGO_PATH
+        );
+    }
+    let decimals = metadata["decimals"]
+        .as_u64()
+        .and_then(|decimals| u8::try_from(decimals).ok())
+        .unwrap_or_else(|| panic!("{}: invalid `decimals`", METADATA_PATH));
+    let reference_hash: [u8; 32] = Sha256::digest(&raw).into();
+
+    let generated = format!(
+        "pub(crate) const DEFAULT_NAME: &str = {:?};\n\
+         pub(crate) const DEFAULT_SYMBOL: &str = {:?};\n\
+         pub(crate) const DEFAULT_DECIMALS: u8 = {};\n\
+         pub(crate) const DEFAULT_ICON: &str = {:?};\n\
+         pub(crate) const DEFAULT_REFERENCE: &str = {:?};\n\
+         pub(crate) const DEFAULT_REFERENCE_HASH: [u8; 32] = {:?};\n",
+        string_field(&metadata, "name"),
+        string_field(&metadata, "symbol"),
+        decimals,
+        icon,
+        string_field(&metadata, "reference"),
+        reference_hash,
+    );
+    let out_dir = env::var("OUT_DIR").expect("OUT_DIR is not set");
+    fs::write(Path::new(&out_dir).join("default_metadata.rs"), generated)
+        .expect("failed to write the default metadata");
+}
@@ -0,0 +1,62 @@
+//! Per-account sets of ids, used to list the entries of a feature an account takes part in.
+use near_sdk::collections::{LookupMap, UnorderedSet};
+use near_sdk::{env, near, AccountId, IntoStorageKey};
+
+#[near]
+pub struct AccountIndex {
+    prefix: Vec<u8>,
+    sets: LookupMap<AccountId, UnorderedSet<u64>>,
+}
+
+impl AccountIndex {
+    pub fn new<S: IntoStorageKey>(prefix: S) -> Self {
+        let prefix = prefix.into_storage_key();
+        Self {
+            sets: LookupMap::new([&prefix[..], b"a"].concat()),
+            prefix,
+        }
+    }
+
+    pub fn insert(&mut self, account_id: &AccountId, id: u64) {
+        let mut set = self.sets.get(account_id).unwrap_or_else(|| {
+            UnorderedSet::new(
+                [
+                    &self.prefix[..],
+                    b"s",
+                    &env::sha256_array(account_id.as_bytes()),
+                ]
+                .concat(),
+            )
+        });
+        set.insert(&id);
+        self.sets.insert(account_id, &set);
+    }
+
+    pub fn remove(&mut self, account_id: &AccountId, id: u64) {
+        if let Some(mut set) = self.sets.get(account_id) {
+            set.remove(&id);
+            if set.is_empty() {
+                self.sets.remove(account_id);
+            } else {
+                self.sets.insert(account_id, &set);
+            }
+        }
+    }
+
+    pub fn ids(
+        &self,
+        account_id: &AccountId,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> Vec<u64> {
+        self.sets
+            .get(account_id)
+            .map(|set| {
+                set.iter()
+                    .skip(from_index.unwrap_or(0) as usize)
+                    .take(limit.unwrap_or(u64::MAX) as usize)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
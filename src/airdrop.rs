@@ -0,0 +1,551 @@
+//! Merkle-root airdrop campaigns.
+//!
+//! The owner locks the total amount of a campaign together with the root of a merkle tree whose
+//! leaves are `sha256("{index}:{account_id}:{amount}")`. Recipients claim their allocation with
+//! the sibling hashes from their leaf up to the root; the bits of `index` tell on which side of
+//! each pair the running hash sits, and a per-campaign bitmap indexed by it prevents double
+//! claims. Once a campaign expires, whatever was not claimed goes back to its treasury account,
+//! or to the owner of the contract if the treasury has since unregistered or been frozen.
+//!
+//! The NEAR attached when creating a campaign is its storage budget: claimers that are not
+//! registered yet get registered out of it, exactly as if they had paid `storage_deposit`
+//! themselves, and what is left is refunded to the creator when the campaign is closed.
+use std::convert::TryInto;
+
+use near_contract_standards::fungible_token::Balance;
+use near_contract_standards::storage_management::StorageManagement;
+use near_sdk::collections::{LookupMap, Vector};
+use near_sdk::json_types::{Base64VecU8, U128, U64};
+use near_sdk::{env, near, require, AccountId, CryptoHash, NearToken, Promise};
+
+use crate::events::Event;
+use crate::internal::assert_at_least_one_yocto;
+use crate::locks::LockBucket;
+use crate::{Contract, ContractExt, StorageKey};
+
+const BITMAP_WORD_BITS: u32 = 128;
+
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct AirdropCampaign {
+    pub id: u64,
+    pub creator: AccountId,
+    pub merkle_root: Base64VecU8,
+    pub total_amount: U128,
+    pub claimed_amount: U128,
+    /// Block timestamp in nanoseconds after which claims are no longer accepted.
+    pub expiry: U64,
+    /// Account receiving the unclaimed tokens once the campaign is closed.
+    pub treasury_id: AccountId,
+    /// NEAR left to pay for the storage registration of claimers.
+    pub storage_budget: NearToken,
+    pub closed: bool,
+}
+
+/// Position of the claimed leaf in the tree and the sibling hashes from the leaf to the root.
+#[near(serializers = [json])]
+pub struct AirdropProof {
+    pub index: u32,
+    pub path: Vec<Base64VecU8>,
+}
+
+#[near]
+pub struct Airdrops {
+    campaigns: Vector<AirdropCampaign>,
+    /// (Campaign id, word index) -> bitmap of claimed leaf indices.
+    claimed: LookupMap<(u64, u32), u128>,
+}
+
+impl Airdrops {
+    pub fn new() -> Self {
+        Self {
+            campaigns: Vector::new(StorageKey::AirdropCampaigns),
+            claimed: LookupMap::new(StorageKey::AirdropClaimed),
+        }
+    }
+
+    fn campaign(&self, campaign_id: u64) -> AirdropCampaign {
+        self.campaigns
+            .get(campaign_id)
+            .unwrap_or_else(|| env::panic_str("ERR_CAMPAIGN_NOT_FOUND"))
+    }
+
+    fn is_claimed(&self, campaign_id: u64, index: u32) -> bool {
+        let word = self
+            .claimed
+            .get(&(campaign_id, index / BITMAP_WORD_BITS))
+            .unwrap_or(0);
+        word & (1 << (index % BITMAP_WORD_BITS)) != 0
+    }
+
+    fn set_claimed(&mut self, campaign_id: u64, index: u32) {
+        let key = (campaign_id, index / BITMAP_WORD_BITS);
+        let word = self.claimed.get(&key).unwrap_or(0);
+        self.claimed
+            .insert(&key, &(word | (1 << (index % BITMAP_WORD_BITS))));
+    }
+}
+
+pub fn airdrop_leaf(index: u32, account_id: &AccountId, amount: Balance) -> CryptoHash {
+    env::sha256_array(format!("{}:{}:{}", index, account_id, amount).as_bytes())
+}
+
+/// Folds `leaf` with the proof path and compares the result with `root`. A `u32` index addresses
+/// at most `u32::BITS` levels, so longer paths are rejected.
+fn verify_proof(root: &[u8], leaf: CryptoHash, proof: &AirdropProof) -> bool {
+    if proof.path.len() > u32::BITS as usize
+        || proof
+            .index
+            .checked_shr(proof.path.len() as u32)
+            .unwrap_or(0)
+            != 0
+    {
+        return false;
+    }
+
+    let mut hash = leaf;
+    for (level, sibling) in proof.path.iter().enumerate() {
+        let mut pair = Vec::with_capacity(64);
+        if proof.index >> level & 1 == 0 {
+            pair.extend_from_slice(&hash);
+            pair.extend_from_slice(&sibling.0);
+        } else {
+            pair.extend_from_slice(&sibling.0);
+            pair.extend_from_slice(&hash);
+        }
+        hash = env::sha256_array(&pair);
+    }
+    hash[..] == *root
+}
+
+#[near]
+impl Contract {
+    /// Creates a campaign funded with `total_amount` tokens of the predecessor. `treasury_id`
+    /// defaults to the predecessor, must be registered and not frozen, and the attached deposit
+    /// becomes the storage budget.
+    #[payable]
+    pub fn airdrop_create(
+        &mut self,
+        merkle_root: Base64VecU8,
+        total_amount: U128,
+        expiry: U64,
+        treasury_id: Option<AccountId>,
+    ) -> u64 {
+        assert_at_least_one_yocto();
+        self.assert_owner();
+        let _: CryptoHash = merkle_root
+            .0
+            .as_slice()
+            .try_into()
+            .unwrap_or_else(|_| env::panic_str("ERR_INVALID_MERKLE_ROOT"));
+        require!(total_amount.0 > 0, "ERR_ZERO_AMOUNT");
+        require!(expiry.0 > env::block_timestamp(), "ERR_EXPIRY_IN_THE_PAST");
+
+        let funder = env::predecessor_account_id();
+        let treasury_id = treasury_id.unwrap_or_else(|| funder.clone());
+        require!(
+            self.token.accounts.contains_key(&treasury_id),
+            "ERR_TREASURY_NOT_REGISTERED"
+        );
+        self.assert_not_frozen(&treasury_id);
+        let id = self.airdrops.campaigns.len();
+
+        self.internal_lock(LockBucket::Airdrop, &funder, total_amount.0, "airdrop");
+        self.airdrops.campaigns.push(&AirdropCampaign {
+            id,
+            creator: funder,
+            merkle_root,
+            total_amount,
+            claimed_amount: 0.into(),
+            expiry,
+            treasury_id,
+            storage_budget: env::attached_deposit(),
+            closed: false,
+        });
+
+        Event::AirdropCreated {
+            campaign_id: id,
+            total_amount,
+            expiry,
+        }
+        .emit();
+        id
+    }
+
+    /// Claims the allocation of the predecessor in `campaign_id`, registering the predecessor
+    /// with the campaign storage budget if needed.
+    pub fn airdrop_claim(&mut self, campaign_id: u64, amount: U128, proof: AirdropProof) {
+        let account_id = env::predecessor_account_id();
+        let mut campaign = self.airdrops.campaign(campaign_id);
+        require!(
+            !campaign.closed && env::block_timestamp() <= campaign.expiry.0,
+            "ERR_CAMPAIGN_EXPIRED"
+        );
+        require!(
+            !self.airdrops.is_claimed(campaign_id, proof.index),
+            "ERR_ALREADY_CLAIMED"
+        );
+        require!(
+            verify_proof(
+                &campaign.merkle_root.0,
+                airdrop_leaf(proof.index, &account_id, amount.0),
+                &proof
+            ),
+            "ERR_INVALID_MERKLE_PROOF"
+        );
+
+        campaign.claimed_amount.0 += amount.0;
+        require!(
+            campaign.claimed_amount.0 <= campaign.total_amount.0,
+            "ERR_CAMPAIGN_EXHAUSTED"
+        );
+        if !self.token.accounts.contains_key(&account_id) {
+            let storage_cost = self.storage_balance_bounds().min;
+            campaign.storage_budget = campaign
+                .storage_budget
+                .checked_sub(storage_cost)
+                .unwrap_or_else(|| env::panic_str("ERR_STORAGE_BUDGET_EXHAUSTED"));
+            self.internal_register_account(&account_id);
+        }
+        self.airdrops.set_claimed(campaign_id, proof.index);
+        self.airdrops.campaigns.replace(campaign_id, &campaign);
+
+        self.internal_unlock(
+            LockBucket::Airdrop,
+            &campaign.creator,
+            &account_id,
+            amount.0,
+            "airdrop claim",
+        );
+        Event::AirdropClaimed {
+            campaign_id,
+            account_id: &account_id,
+            amount,
+        }
+        .emit();
+    }
+
+    /// Returns the unclaimed tokens of an expired campaign to its treasury, or to the owner if the
+    /// treasury can no longer receive them, and the rest of the storage budget to its creator.
+    /// Anyone can call it.
+    pub fn airdrop_close(&mut self, campaign_id: u64) -> U128 {
+        let mut campaign = self.airdrops.campaign(campaign_id);
+        require!(!campaign.closed, "ERR_CAMPAIGN_CLOSED");
+        require!(
+            env::block_timestamp() > campaign.expiry.0,
+            "ERR_CAMPAIGN_NOT_EXPIRED"
+        );
+
+        let unclaimed = campaign.total_amount.0 - campaign.claimed_amount.0;
+        let storage_budget = campaign.storage_budget;
+        campaign.closed = true;
+        campaign.storage_budget = NearToken::from_yoctonear(0);
+        self.airdrops.campaigns.replace(campaign_id, &campaign);
+
+        if !storage_budget.is_zero() {
+            let _ = Promise::new(campaign.creator.clone()).transfer(storage_budget);
+        }
+        let treasury_id = if self.token.accounts.contains_key(&campaign.treasury_id)
+            && !self.compliance.is_frozen(&campaign.treasury_id)
+        {
+            campaign.treasury_id
+        } else {
+            self.owner.clone()
+        };
+        self.internal_unlock(
+            LockBucket::Airdrop,
+            &campaign.creator,
+            &treasury_id,
+            unclaimed,
+            "airdrop unclaimed",
+        );
+        Event::AirdropClosed {
+            campaign_id,
+            treasury_id: &treasury_id,
+            unclaimed_amount: unclaimed.into(),
+        }
+        .emit();
+        unclaimed.into()
+    }
+
+    pub fn airdrop_campaign(&self, campaign_id: u64) -> Option<AirdropCampaign> {
+        self.airdrops.campaigns.get(campaign_id)
+    }
+
+    pub fn airdrop_campaigns(
+        &self,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> Vec<AirdropCampaign> {
+        self.airdrops
+            .campaigns
+            .iter()
+            .skip(from_index.unwrap_or(0) as usize)
+            .take(limit.unwrap_or(u64::MAX) as usize)
+            .collect()
+    }
+
+    pub fn airdrop_is_claimed(&self, campaign_id: u64, index: u32) -> bool {
+        self.airdrops.is_claimed(campaign_id, index)
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_contract_standards::fungible_token::FungibleTokenCore;
+    use near_sdk::test_utils::VMContextBuilder;
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_utils::*;
+
+    const EXPIRY: u64 = 1_000;
+
+    fn hash_pair(left: &CryptoHash, right: &CryptoHash) -> CryptoHash {
+        env::sha256_array(&[&left[..], &right[..]].concat())
+    }
+
+    /// Builds a four-leaf tree and returns the root with the proof of every leaf.
+    fn tree(allocations: &[(AccountId, Balance); 4]) -> (Base64VecU8, Vec<AirdropProof>) {
+        let leaves: Vec<CryptoHash> = allocations
+            .iter()
+            .enumerate()
+            .map(|(index, (account_id, amount))| airdrop_leaf(index as u32, account_id, *amount))
+            .collect();
+        let nodes = [
+            hash_pair(&leaves[0], &leaves[1]),
+            hash_pair(&leaves[2], &leaves[3]),
+        ];
+        let root = hash_pair(&nodes[0], &nodes[1]);
+
+        let proofs = (0..4)
+            .map(|index| AirdropProof {
+                index: index as u32,
+                path: vec![
+                    leaves[index ^ 1].to_vec().into(),
+                    nodes[(index / 2) ^ 1].to_vec().into(),
+                ],
+            })
+            .collect();
+        (root.to_vec().into(), proofs)
+    }
+
+    fn setup_campaign() -> (Contract, VMContextBuilder, Vec<AirdropProof>) {
+        let (mut contract, mut context) = setup();
+        register(&mut contract, &mut context, user1());
+        register(&mut contract, &mut context, user2());
+        create_campaign(&mut contract, &mut context, ONE_YOCTO);
+
+        (contract, context, proofs())
+    }
+
+    fn proofs() -> Vec<AirdropProof> {
+        tree(&[
+            (user1(), 100),
+            (user2(), 200),
+            (user1(), 300),
+            (owner(), 400),
+        ])
+        .1
+    }
+
+    fn create_campaign(
+        contract: &mut Contract,
+        context: &mut VMContextBuilder,
+        storage_budget: NearToken,
+    ) {
+        let (root, _) = tree(&[
+            (user1(), 100),
+            (user2(), 200),
+            (user1(), 300),
+            (owner(), 400),
+        ]);
+        testing_env!(context
+            .predecessor_account_id(owner())
+            .attached_deposit(storage_budget)
+            .build());
+        contract.airdrop_create(root, 1_000.into(), EXPIRY.into(), None);
+    }
+
+    fn claim(
+        contract: &mut Contract,
+        context: &mut VMContextBuilder,
+        account_id: AccountId,
+        amount: Balance,
+        proof: AirdropProof,
+    ) {
+        testing_env!(context.predecessor_account_id(account_id).build());
+        contract.airdrop_claim(0, amount.into(), proof);
+    }
+
+    #[test]
+    fn test_claim() {
+        let (mut contract, mut context, mut proofs) = setup_campaign();
+        assert_eq!(contract.ft_balance_of(owner()).0, TOTAL_SUPPLY - 1_000);
+        assert_eq!(contract.locks.bucket(LockBucket::Airdrop), 1_000);
+
+        claim(&mut contract, &mut context, user1(), 300, proofs.remove(2));
+        claim(&mut contract, &mut context, user2(), 200, proofs.remove(1));
+
+        assert_eq!(contract.ft_balance_of(user1()).0, 300);
+        assert_eq!(contract.ft_balance_of(user2()).0, 200);
+        assert!(contract.airdrop_is_claimed(0, 1));
+        assert!(contract.airdrop_is_claimed(0, 2));
+        assert!(!contract.airdrop_is_claimed(0, 0));
+        assert_eq!(contract.airdrop_campaign(0).unwrap().claimed_amount.0, 500);
+    }
+
+    #[should_panic(expected = "ERR_ALREADY_CLAIMED")]
+    #[test]
+    fn test_claim_panics_on_double_claim() {
+        let (mut contract, mut context, proofs) = setup_campaign();
+
+        for _ in 0..2 {
+            let proof = AirdropProof {
+                index: proofs[0].index,
+                path: proofs[0].path.clone(),
+            };
+            claim(&mut contract, &mut context, user1(), 100, proof);
+        }
+    }
+
+    #[should_panic(expected = "ERR_INVALID_MERKLE_PROOF")]
+    #[test]
+    fn test_claim_panics_on_wrong_amount() {
+        let (mut contract, mut context, mut proofs) = setup_campaign();
+
+        claim(
+            &mut contract,
+            &mut context,
+            user1(),
+            1_000,
+            proofs.remove(0),
+        );
+    }
+
+    #[should_panic(expected = "ERR_INVALID_MERKLE_PROOF")]
+    #[test]
+    fn test_claim_panics_on_someone_elses_leaf() {
+        let (mut contract, mut context, mut proofs) = setup_campaign();
+
+        claim(&mut contract, &mut context, user2(), 100, proofs.remove(0));
+    }
+
+    #[test]
+    fn test_verify_proof_rejects_long_paths() {
+        let (_, proofs) = tree(&[
+            (user1(), 100),
+            (user2(), 200),
+            (user1(), 300),
+            (owner(), 400),
+        ]);
+        let leaf = airdrop_leaf(u32::MAX, &user1(), 100);
+        for depth in [32, 33, 64] {
+            let proof = AirdropProof {
+                index: u32::MAX,
+                path: vec![proofs[0].path[0].clone(); depth],
+            };
+            assert!(!verify_proof(&[0; 32], leaf, &proof));
+        }
+    }
+
+    #[should_panic(expected = "ERR_CAMPAIGN_EXPIRED")]
+    #[test]
+    fn test_claim_panics_after_expiry() {
+        let (mut contract, mut context, mut proofs) = setup_campaign();
+
+        testing_env!(context.block_timestamp(EXPIRY + 1).build());
+        claim(&mut contract, &mut context, user1(), 100, proofs.remove(0));
+    }
+
+    #[test]
+    fn test_close_returns_unclaimed_to_treasury() {
+        let (mut contract, mut context, mut proofs) = setup_campaign();
+        claim(&mut contract, &mut context, user1(), 100, proofs.remove(0));
+
+        testing_env!(context
+            .block_timestamp(EXPIRY + 1)
+            .predecessor_account_id(user2())
+            .build());
+        assert_eq!(contract.airdrop_close(0).0, 900);
+
+        assert_eq!(contract.ft_balance_of(owner()).0, TOTAL_SUPPLY - 100);
+        assert_eq!(contract.locks.bucket(LockBucket::Airdrop), 0);
+        assert!(contract.airdrop_campaign(0).unwrap().closed);
+    }
+
+    #[test]
+    fn test_close_falls_back_to_owner_when_treasury_frozen() {
+        let (mut contract, mut context) = setup();
+        register(&mut contract, &mut context, user1());
+        let (root, _) = tree(&[
+            (user1(), 100),
+            (user2(), 200),
+            (user1(), 300),
+            (owner(), 400),
+        ]);
+        testing_env!(context
+            .predecessor_account_id(owner())
+            .attached_deposit(ONE_YOCTO)
+            .build());
+        contract.airdrop_create(root, 1_000.into(), EXPIRY.into(), Some(user1()));
+        freeze(&mut contract, &mut context, user1());
+
+        testing_env!(context.block_timestamp(EXPIRY + 1).build());
+        assert_eq!(contract.airdrop_close(0).0, 1_000);
+        assert_eq!(contract.ft_balance_of(owner()).0, TOTAL_SUPPLY);
+        assert_eq!(contract.ft_balance_of(user1()).0, 0);
+    }
+
+    #[should_panic(expected = "ERR_TREASURY_NOT_REGISTERED")]
+    #[test]
+    fn test_create_panics_for_unregistered_treasury() {
+        let (mut contract, mut context) = setup();
+
+        testing_env!(context
+            .predecessor_account_id(owner())
+            .attached_deposit(ONE_YOCTO)
+            .build());
+        contract.airdrop_create(
+            vec![0; 32].into(),
+            1_000.into(),
+            EXPIRY.into(),
+            Some(user1()),
+        );
+    }
+
+    #[test]
+    fn test_claim_registers_new_account() {
+        let (mut contract, mut context) = setup();
+        let storage_cost = contract.storage_balance_bounds().min;
+        create_campaign(&mut contract, &mut context, storage_cost.saturating_mul(3));
+        let mut proofs = proofs();
+
+        claim(&mut contract, &mut context, user2(), 200, proofs.remove(1));
+
+        assert!(contract.storage_balance_of(user2()).is_some());
+        assert_eq!(contract.ft_balance_of(user2()).0, 200);
+        assert_eq!(
+            contract.airdrop_campaign(0).unwrap().storage_budget,
+            storage_cost.saturating_mul(2)
+        );
+    }
+
+    #[should_panic(expected = "ERR_STORAGE_BUDGET_EXHAUSTED")]
+    #[test]
+    fn test_claim_panics_when_storage_budget_exhausted() {
+        let (mut contract, mut context) = setup();
+        create_campaign(&mut contract, &mut context, ONE_YOCTO);
+        let mut proofs = proofs();
+
+        claim(&mut contract, &mut context, user2(), 200, proofs.remove(1));
+    }
+
+    #[should_panic(expected = "ERR_CAMPAIGN_NOT_EXPIRED")]
+    #[test]
+    fn test_close_panics_before_expiry() {
+        let (mut contract, _, _) = setup_campaign();
+
+        contract.airdrop_close(0);
+    }
+}
@@ -0,0 +1,72 @@
+//! Batch views, so wallets can read many accounts in one RPC round-trip.
+use near_contract_standards::fungible_token::FungibleTokenCore;
+use near_contract_standards::storage_management::{StorageBalance, StorageManagement};
+use near_sdk::json_types::U128;
+use near_sdk::{near, require, AccountId};
+
+use crate::{Contract, ContractExt};
+
+/// Maximum number of accounts a batch view accepts, which keeps it well within the view gas limit.
+pub const MAX_BATCH_ACCOUNTS: usize = 100;
+
+fn assert_batch_size(account_ids: &[AccountId]) {
+    require!(
+        account_ids.len() <= MAX_BATCH_ACCOUNTS,
+        "ERR_TOO_MANY_ACCOUNTS"
+    );
+}
+
+#[near]
+impl Contract {
+    /// Returns the balances of `account_ids`, in the same order. Unregistered accounts have a
+    /// zero balance.
+    pub fn ft_balances_of(&self, account_ids: Vec<AccountId>) -> Vec<U128> {
+        assert_batch_size(&account_ids);
+        account_ids
+            .into_iter()
+            .map(|account_id| self.ft_balance_of(account_id))
+            .collect()
+    }
+
+    /// Returns the storage balances of `account_ids`, in the same order, `None` standing for an
+    /// unregistered account.
+    pub fn storage_balances_of(&self, account_ids: Vec<AccountId>) -> Vec<Option<StorageBalance>> {
+        assert_batch_size(&account_ids);
+        account_ids
+            .into_iter()
+            .map(|account_id| self.storage_balance_of(account_id))
+            .collect()
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    #[test]
+    fn test_batch_views() {
+        let (mut contract, mut context) = setup();
+        register(&mut contract, &mut context, user1());
+        transfer(&mut contract, &mut context, owner(), user1(), 100);
+
+        assert_eq!(
+            contract.ft_balances_of(vec![user1(), user2(), owner()]),
+            vec![U128(100), U128(0), U128(TOTAL_SUPPLY - 100)]
+        );
+        let storage_balances = contract.storage_balances_of(vec![user2(), user1()]);
+        assert!(storage_balances[0].is_none());
+        assert_eq!(
+            storage_balances[1].as_ref().unwrap().total,
+            contract.storage_balance_bounds().min
+        );
+    }
+
+    #[should_panic(expected = "ERR_TOO_MANY_ACCOUNTS")]
+    #[test]
+    fn test_batch_views_panic_above_max() {
+        let (contract, _) = setup();
+
+        contract.ft_balances_of(vec![user1(); MAX_BATCH_ACCOUNTS + 1]);
+    }
+}
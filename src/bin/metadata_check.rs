@@ -0,0 +1,205 @@
+//! Checks the metadata files before a deployment.
+//!
+//! Computes the `reference_hash` of `metadata.json`, validates the metadata of `init.json` like
+//! the contract does at initialization, checks that the icon is a well-formed data URI and
+//! reports every field where `init.json`, `metadata.json` and the metadata compiled into
+//! `new_default_meta` disagree. Exits with a non-zero status when anything is reported.
+//!
+//! Usage: `cargo run --features metadata-tools --bin metadata-check [metadata.json] [init.json]`
+use std::fs;
+use std::panic;
+use std::process;
+
+use base64::{engine::general_purpose, Engine};
+use fungible_token::default_metadata;
+use near_contract_standards::fungible_token::metadata::FungibleTokenMetadata;
+use near_sdk::env;
+use near_sdk::json_types::Base64VecU8;
+use near_sdk::serde_json::{self, Value};
+
+const SVG_DATA_URI_PREFIX: &str = "data:image/svg+xml,";
+
+struct Report {
+    problems: usize,
+}
+
+impl Report {
+    fn ok(&self, message: &str) {
+        println!("ok: {}", message);
+    }
+
+    fn fail(&mut self, message: &str) {
+        self.problems += 1;
+        println!("mismatch: {}", message);
+    }
+
+    fn compare<T: PartialEq + std::fmt::Debug>(&mut self, what: &str, left: &T, right: &T) {
+        if left == right {
+            self.ok(what);
+        } else {
+            self.fail(&format!("{}: {:?} != {:?}", what, left, right));
+        }
+    }
+}
+
+fn read(path: &str) -> Vec<u8> {
+    fs::read(path).unwrap_or_else(|err| {
+        eprintln!("cannot read {}: {}", path, err);
+        process::exit(2);
+    })
+}
+
+fn percent_decode(value: &str) -> Option<String> {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = value.get(i + 1..i + 3)?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+/// Returns why `icon` is not a usable data URI, if it is not.
+fn check_icon(icon: &str) -> Result<(), String> {
+    if let Some(svg) = icon.strip_prefix(SVG_DATA_URI_PREFIX) {
+        let svg = percent_decode(svg).ok_or("invalid percent-encoding")?;
+        if !svg.trim_start().starts_with("<svg") || !svg.trim_end().ends_with("</svg>") {
+            return Err("the decoded content is not an SVG document".to_string());
+        }
+        return Ok(());
+    }
+    match icon.split_once(";base64,") {
+        Some((media_type, data)) if media_type.starts_with("data:image/") => {
+            general_purpose::STANDARD
+                .decode(data)
+                .map(|_| ())
+                .map_err(|err| format!("invalid base64 content: {}", err))
+        }
+        _ => Err("not an image data URI".to_string()),
+    }
+}
+
+/// Runs `assert_valid`, returning its panic message instead of aborting.
+fn validate(metadata: &FungibleTokenMetadata) -> Result<(), String> {
+    panic::set_hook(Box::new(|_| {}));
+    let result = panic::catch_unwind(|| metadata.assert_valid());
+    let _ = panic::take_hook();
+    result.map_err(|payload| {
+        payload
+            .downcast_ref::<String>()
+            .cloned()
+            .or_else(|| payload.downcast_ref::<&str>().map(|msg| msg.to_string()))
+            .unwrap_or_else(|| "assertion failed".to_string())
+    })
+}
+
+fn encode_hash(hash: &Option<Base64VecU8>) -> Option<String> {
+    hash.as_ref()
+        .map(|hash| general_purpose::STANDARD.encode(&hash.0))
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let metadata_path = args.first().map_or("metadata.json", String::as_str);
+    let init_path = args.get(1).map_or("init.json", String::as_str);
+
+    let metadata_file = read(metadata_path);
+    let hash = general_purpose::STANDARD.encode(env::sha256(&metadata_file));
+    println!("reference_hash of {}: {}", metadata_path, hash);
+
+    let document: Value = serde_json::from_slice(&metadata_file).unwrap_or_else(|err| {
+        eprintln!("cannot parse {}: {}", metadata_path, err);
+        process::exit(2);
+    });
+    let init: Value = serde_json::from_slice(&read(init_path)).unwrap_or_else(|err| {
+        eprintln!("cannot parse {}: {}", init_path, err);
+        process::exit(2);
+    });
+    let metadata: FungibleTokenMetadata = serde_json::from_value(init["metadata"].clone())
+        .unwrap_or_else(|err| {
+            eprintln!("invalid metadata in {}: {}", init_path, err);
+            process::exit(2);
+        });
+    let default = default_metadata();
+
+    let mut report = Report { problems: 0 };
+    match validate(&metadata) {
+        Ok(()) => report.ok(&format!("{} metadata is valid", init_path)),
+        Err(err) => report.fail(&format!("{} metadata is invalid: {}", init_path, err)),
+    }
+    for (name, icon) in [("init.json", &metadata.icon), ("default", &default.icon)] {
+        match icon.as_deref().map(check_icon) {
+            Some(Ok(())) => report.ok(&format!("{} icon is a valid data URI", name)),
+            Some(Err(err)) => report.fail(&format!("{} icon: {}", name, err)),
+            None => report.ok(&format!("{} has no icon", name)),
+        }
+    }
+
+    report.compare(
+        "init.json reference_hash matches metadata.json",
+        &encode_hash(&metadata.reference_hash),
+        &Some(hash.clone()),
+    );
+    report.compare(
+        "default reference_hash matches metadata.json",
+        &encode_hash(&default.reference_hash),
+        &Some(hash),
+    );
+
+    for (field, value) in [
+        ("name", Value::from(metadata.name.clone())),
+        ("symbol", Value::from(metadata.symbol.clone())),
+        ("decimals", Value::from(metadata.decimals)),
+        ("icon", Value::from(metadata.icon.clone())),
+        ("reference", Value::from(metadata.reference.clone())),
+    ] {
+        report.compare(
+            &format!("init.json {} matches metadata.json", field),
+            &value,
+            &document[field],
+        );
+    }
+
+    report.compare(
+        "init.json spec matches default",
+        &metadata.spec,
+        &default.spec,
+    );
+    report.compare(
+        "init.json name matches default",
+        &metadata.name,
+        &default.name,
+    );
+    report.compare(
+        "init.json symbol matches default",
+        &metadata.symbol,
+        &default.symbol,
+    );
+    report.compare(
+        "init.json decimals match default",
+        &metadata.decimals,
+        &default.decimals,
+    );
+    report.compare(
+        "init.json icon matches default",
+        &metadata.icon,
+        &default.icon,
+    );
+    report.compare(
+        "init.json reference matches default",
+        &metadata.reference,
+        &default.reference,
+    );
+
+    if report.problems > 0 {
+        println!("{} problem(s) found", report.problems);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,384 @@
+//! Unidirectional payment channels for metered sessions.
+//!
+//! A user locks tokens for a provider together with the ed25519 key they will sign receipts
+//! with. Off-chain, the user signs a [`ChannelReceipt`] with the cumulative amount owed after
+//! each request; the provider only needs to submit the latest one to claim everything owed so
+//! far. The user can close the channel and take the rest back, but only after a challenge
+//! period during which the provider can still submit its last receipt. The provider can close
+//! the channel at any time.
+use near_contract_standards::fungible_token::Balance;
+use near_sdk::collections::LookupMap;
+use near_sdk::json_types::{Base64VecU8, U128, U64};
+use near_sdk::{assert_one_yocto, borsh, env, near, require, AccountId, CurveType, PublicKey};
+
+use crate::account_index::AccountIndex;
+use crate::events::Event;
+use crate::internal::{
+    assert_at_least_one_yocto, refund_deposit, refund_released_storage, verify_ed25519,
+};
+use crate::locks::LockBucket;
+use crate::{Contract, ContractExt, StorageKey};
+
+/// Time the provider has to submit its last receipt once the user asked to close, in nanoseconds.
+pub const CHANNEL_CHALLENGE_PERIOD: u64 = 24 * 60 * 60 * 1_000_000_000;
+
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Channel {
+    pub id: u64,
+    pub user_id: AccountId,
+    pub provider_id: AccountId,
+    /// Key the user signs receipts with.
+    pub public_key: PublicKey,
+    pub deposit: U128,
+    /// Cumulative amount already paid to the provider.
+    pub claimed: U128,
+    /// Block timestamp in nanoseconds after which the user can close the channel.
+    pub closes_at: Option<U64>,
+}
+
+/// Payload the user signs off-chain for every metered request.
+#[near(serializers = [borsh])]
+pub struct ChannelReceipt {
+    pub contract_id: AccountId,
+    pub channel_id: u64,
+    /// Total amount owed to the provider since the channel was opened.
+    pub amount: u128,
+}
+
+#[near]
+pub struct Channels {
+    channels: LookupMap<u64, Channel>,
+    by_account: AccountIndex,
+    next_id: u64,
+}
+
+impl Channels {
+    pub fn new() -> Self {
+        Self {
+            channels: LookupMap::new(StorageKey::Channels),
+            by_account: AccountIndex::new(StorageKey::ChannelsByAccount),
+            next_id: 0,
+        }
+    }
+
+    fn channel(&self, channel_id: u64) -> Channel {
+        self.channels
+            .get(&channel_id)
+            .unwrap_or_else(|| env::panic_str("ERR_CHANNEL_NOT_FOUND"))
+    }
+
+    fn remove(&mut self, channel: &Channel) {
+        self.channels.remove(&channel.id);
+        self.by_account.remove(&channel.user_id, channel.id);
+        self.by_account.remove(&channel.provider_id, channel.id);
+    }
+}
+
+#[near]
+impl Contract {
+    /// Locks `amount` tokens of the predecessor for `provider_id`, payable against receipts
+    /// signed with `public_key`. The attached deposit pays for the channel storage.
+    #[payable]
+    pub fn channel_open(
+        &mut self,
+        provider_id: AccountId,
+        amount: U128,
+        public_key: PublicKey,
+    ) -> u64 {
+        assert_at_least_one_yocto();
+        let initial_storage_usage = env::storage_usage();
+        let user_id = env::predecessor_account_id();
+        require!(user_id != provider_id, "ERR_CHANNEL_TO_SELF");
+        require!(amount.0 > 0, "ERR_ZERO_AMOUNT");
+        require!(
+            public_key.curve_type() == CurveType::ED25519,
+            "ERR_UNSUPPORTED_KEY_TYPE"
+        );
+
+        self.internal_lock(LockBucket::Channel, &user_id, amount.0, "channel deposit");
+
+        let id = self.channels.next_id;
+        self.channels.next_id += 1;
+        let channel = Channel {
+            id,
+            user_id,
+            provider_id,
+            public_key,
+            deposit: amount,
+            claimed: 0.into(),
+            closes_at: None,
+        };
+        self.channels.channels.insert(&id, &channel);
+        self.channels.by_account.insert(&channel.user_id, id);
+        self.channels.by_account.insert(&channel.provider_id, id);
+
+        Event::ChannelOpened {
+            channel_id: id,
+            user_id: &channel.user_id,
+            provider_id: &channel.provider_id,
+            amount,
+        }
+        .emit();
+
+        refund_deposit(initial_storage_usage);
+        id
+    }
+
+    /// Pays the provider up to the cumulative `amount` of a receipt signed by the user.
+    /// Returns the amount paid by this call.
+    #[payable]
+    pub fn channel_claim(&mut self, channel_id: u64, amount: U128, signature: Base64VecU8) -> U128 {
+        assert_one_yocto();
+        let mut channel = self.channels.channel(channel_id);
+        require!(
+            env::predecessor_account_id() == channel.provider_id,
+            "ERR_NOT_CHANNEL_PROVIDER"
+        );
+        require!(amount.0 > channel.claimed.0, "ERR_RECEIPT_ALREADY_CLAIMED");
+        require!(amount.0 <= channel.deposit.0, "ERR_RECEIPT_EXCEEDS_DEPOSIT");
+
+        let message = borsh::to_vec(&ChannelReceipt {
+            contract_id: env::current_account_id(),
+            channel_id,
+            amount: amount.0,
+        })
+        .unwrap_or_else(|_| env::panic_str("ERR_FAILED_TO_SERIALIZE_RECEIPT"));
+        require!(
+            verify_ed25519(&channel.public_key, &message, &signature),
+            "ERR_INVALID_RECEIPT_SIGNATURE"
+        );
+
+        let payment = amount.0 - channel.claimed.0;
+        channel.claimed = amount;
+        self.channels.channels.insert(&channel_id, &channel);
+
+        self.internal_unlock(
+            LockBucket::Channel,
+            &channel.user_id,
+            &channel.provider_id,
+            payment,
+            "channel claim",
+        );
+        Event::ChannelClaimed {
+            channel_id,
+            provider_id: &channel.provider_id,
+            amount: payment.into(),
+            total_claimed: amount,
+        }
+        .emit();
+
+        payment.into()
+    }
+
+    /// Starts the challenge period after which the user can close the channel.
+    #[payable]
+    pub fn channel_start_close(&mut self, channel_id: u64) -> U64 {
+        assert_one_yocto();
+        let mut channel = self.channels.channel(channel_id);
+        require!(
+            env::predecessor_account_id() == channel.user_id,
+            "ERR_NOT_CHANNEL_USER"
+        );
+        require!(channel.closes_at.is_none(), "ERR_CHANNEL_ALREADY_CLOSING");
+
+        let closes_at = U64(env::block_timestamp() + CHANNEL_CHALLENGE_PERIOD);
+        channel.closes_at = Some(closes_at);
+        self.channels.channels.insert(&channel_id, &channel);
+        Event::ChannelCloseStarted {
+            channel_id,
+            closes_at,
+        }
+        .emit();
+
+        closes_at
+    }
+
+    /// Removes the channel and refunds the unclaimed deposit to the user. The provider can
+    /// close it at any time, the user only once the challenge period is over.
+    #[payable]
+    pub fn channel_close(&mut self, channel_id: u64) {
+        assert_one_yocto();
+        let initial_storage_usage = env::storage_usage();
+        let channel = self.channels.channel(channel_id);
+        let account_id = env::predecessor_account_id();
+        if account_id == channel.user_id {
+            require!(
+                channel
+                    .closes_at
+                    .is_some_and(|closes_at| env::block_timestamp() >= closes_at.0),
+                "ERR_CHALLENGE_PERIOD_NOT_OVER"
+            );
+        } else {
+            require!(account_id == channel.provider_id, "ERR_NOT_CHANNEL_PARTY");
+        }
+
+        let refund: Balance = channel.deposit.0 - channel.claimed.0;
+        self.channels.remove(&channel);
+        self.internal_unlock(
+            LockBucket::Channel,
+            &channel.user_id,
+            &channel.user_id,
+            refund,
+            "channel refund",
+        );
+        Event::ChannelClosed {
+            channel_id,
+            claimed: channel.claimed,
+            refund: refund.into(),
+        }
+        .emit();
+
+        refund_released_storage(&channel.user_id, initial_storage_usage);
+    }
+
+    pub fn channel(&self, channel_id: u64) -> Option<Channel> {
+        self.channels.channels.get(&channel_id)
+    }
+
+    /// Lists the open channels `account_id` is the user or the provider of.
+    pub fn channels_of(
+        &self,
+        account_id: AccountId,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> Vec<Channel> {
+        self.channels
+            .by_account
+            .ids(&account_id, from_index, limit)
+            .into_iter()
+            .filter_map(|id| self.channels.channels.get(&id))
+            .collect()
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use ed25519_dalek::{Signer, SigningKey};
+    use near_contract_standards::fungible_token::FungibleTokenCore;
+    use near_sdk::test_utils::VMContextBuilder;
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_utils::*;
+
+    const DEPOSIT: Balance = 1_000;
+
+    fn signing_key() -> SigningKey {
+        SigningKey::from_bytes(&[9; 32])
+    }
+
+    fn sign(channel_id: u64, amount: Balance) -> Base64VecU8 {
+        let message = borsh::to_vec(&ChannelReceipt {
+            contract_id: current(),
+            channel_id,
+            amount,
+        })
+        .unwrap();
+        signing_key().sign(&message).to_bytes().to_vec().into()
+    }
+
+    fn setup_channel() -> (Contract, VMContextBuilder) {
+        let (mut contract, mut context) = setup();
+        register(&mut contract, &mut context, user1());
+
+        let public_key = PublicKey::from_parts(
+            CurveType::ED25519,
+            signing_key().verifying_key().to_bytes().to_vec(),
+        )
+        .unwrap();
+        testing_env!(context
+            .block_timestamp(0)
+            .predecessor_account_id(owner())
+            .attached_deposit(ONE_NEAR)
+            .build());
+        contract.channel_open(user1(), DEPOSIT.into(), public_key);
+
+        (contract, context)
+    }
+
+    #[test]
+    fn test_claim_cumulative_receipts() {
+        let (mut contract, mut context) = setup_channel();
+        assert_eq!(contract.ft_balance_of(owner()).0, TOTAL_SUPPLY - DEPOSIT);
+
+        at(&mut context, 0, user1());
+        assert_eq!(contract.channel_claim(0, 100.into(), sign(0, 100)).0, 100);
+        assert_eq!(contract.channel_claim(0, 250.into(), sign(0, 250)).0, 150);
+
+        assert_eq!(contract.ft_balance_of(user1()).0, 250);
+        assert_eq!(contract.channel(0).unwrap().claimed.0, 250);
+        assert_eq!(contract.locks.bucket(LockBucket::Channel), DEPOSIT - 250);
+    }
+
+    #[should_panic(expected = "ERR_ACCOUNT_FROZEN")]
+    #[test]
+    fn test_claim_panics_when_user_frozen() {
+        let (mut contract, mut context) = setup_channel();
+        freeze(&mut contract, &mut context, owner());
+
+        at(&mut context, 0, user1());
+        contract.channel_claim(0, 100.into(), sign(0, 100));
+    }
+
+    #[should_panic(expected = "ERR_ACCOUNT_FROZEN")]
+    #[test]
+    fn test_claim_panics_when_provider_frozen() {
+        let (mut contract, mut context) = setup_channel();
+        freeze(&mut contract, &mut context, user1());
+
+        at(&mut context, 0, user1());
+        contract.channel_claim(0, 100.into(), sign(0, 100));
+    }
+
+    #[should_panic(expected = "ERR_RECEIPT_ALREADY_CLAIMED")]
+    #[test]
+    fn test_claim_panics_on_stale_receipt() {
+        let (mut contract, mut context) = setup_channel();
+
+        at(&mut context, 0, user1());
+        contract.channel_claim(0, 250.into(), sign(0, 250));
+        contract.channel_claim(0, 100.into(), sign(0, 100));
+    }
+
+    #[should_panic(expected = "ERR_INVALID_RECEIPT_SIGNATURE")]
+    #[test]
+    fn test_claim_panics_on_forged_amount() {
+        let (mut contract, mut context) = setup_channel();
+
+        at(&mut context, 0, user1());
+        contract.channel_claim(0, 900.into(), sign(0, 100));
+    }
+
+    #[test]
+    fn test_user_closes_after_challenge_period() {
+        let (mut contract, mut context) = setup_channel();
+
+        at(&mut context, 0, owner());
+        contract.channel_start_close(0);
+
+        // the provider can still claim during the challenge period
+        at(&mut context, CHANNEL_CHALLENGE_PERIOD - 1, user1());
+        contract.channel_claim(0, 400.into(), sign(0, 400));
+
+        at(&mut context, CHANNEL_CHALLENGE_PERIOD, owner());
+        contract.channel_close(0);
+
+        assert_eq!(contract.ft_balance_of(owner()).0, TOTAL_SUPPLY - 400);
+        assert_eq!(contract.ft_balance_of(user1()).0, 400);
+        assert!(contract.channel(0).is_none());
+        assert!(contract.channels_of(owner(), None, None).is_empty());
+    }
+
+    #[should_panic(expected = "ERR_CHALLENGE_PERIOD_NOT_OVER")]
+    #[test]
+    fn test_user_close_panics_during_challenge_period() {
+        let (mut contract, mut context) = setup_channel();
+
+        at(&mut context, 0, owner());
+        contract.channel_start_close(0);
+
+        at(&mut context, CHANNEL_CHALLENGE_PERIOD - 1, owner());
+        contract.channel_close(0);
+    }
+}
@@ -0,0 +1,493 @@
+//! Compliance officers and frozen accounts.
+//!
+//! The owner appoints compliance officers, who can freeze the accounts implicated in a hack or a
+//! legal case. A frozen account cannot send or receive tokens: it cannot transfer them, lock them
+//! into the features of the contract or receive them from a transfer or a payout of those
+//! features, no payout can be made on its behalf from the tokens it locked, and it cannot force
+//! its storage unregistration, which would burn the evidence along with the balance.
+//! Every freeze and unfreeze emits an event with the reason given by the officer.
+//!
+//! Officers can also seize the tokens of a frozen account, for example to return stolen funds
+//! under a court order. A seizure is scheduled first and can only be executed once
+//! [`SEIZURE_TIMELOCK`] has passed, while the account is still frozen, which leaves time to
+//! cancel it. All seizures are kept, whatever their outcome, as the audit trail of the cases.
+use near_sdk::collections::{UnorderedMap, UnorderedSet, Vector};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::{assert_one_yocto, env, near, require, AccountId};
+
+use crate::events::Event;
+use crate::{Contract, ContractExt, StorageKey};
+
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Freeze {
+    pub officer_id: AccountId,
+    pub reason: String,
+    pub frozen_at: U64,
+}
+
+/// Delay between scheduling a seizure and executing it, in nanoseconds.
+pub const SEIZURE_TIMELOCK: u64 = 2 * 24 * 60 * 60 * 1_000_000_000;
+
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SeizureStatus {
+    Scheduled,
+    Executed,
+    Canceled,
+}
+
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Seizure {
+    pub id: u64,
+    pub from: AccountId,
+    pub to: AccountId,
+    pub amount: U128,
+    /// Reference of the legal case, such as a court order number.
+    pub case_ref: String,
+    pub officer_id: AccountId,
+    pub scheduled_at: U64,
+    /// Block timestamp in nanoseconds from which the seizure can be executed.
+    pub executable_at: U64,
+    pub status: SeizureStatus,
+}
+
+#[near]
+pub struct Compliance {
+    officers: UnorderedSet<AccountId>,
+    frozen: UnorderedMap<AccountId, Freeze>,
+    seizures: Vector<Seizure>,
+}
+
+impl Compliance {
+    pub fn new() -> Self {
+        Self {
+            officers: UnorderedSet::new(StorageKey::ComplianceOfficers),
+            frozen: UnorderedMap::new(StorageKey::FrozenAccounts),
+            seizures: Vector::new(StorageKey::Seizures),
+        }
+    }
+
+    fn scheduled_seizure(&self, seizure_id: u64) -> Seizure {
+        let seizure = self
+            .seizures
+            .get(seizure_id)
+            .unwrap_or_else(|| env::panic_str("ERR_SEIZURE_NOT_FOUND"));
+        require!(
+            seizure.status == SeizureStatus::Scheduled,
+            "ERR_SEIZURE_NOT_SCHEDULED"
+        );
+        seizure
+    }
+
+    pub fn is_frozen(&self, account_id: &AccountId) -> bool {
+        self.frozen.get(account_id).is_some()
+    }
+}
+
+impl Contract {
+    pub(crate) fn assert_compliance_officer(&self) {
+        require!(
+            self.compliance
+                .officers
+                .contains(&env::predecessor_account_id()),
+            "ERR_NOT_COMPLIANCE_OFFICER"
+        );
+    }
+
+    pub(crate) fn assert_not_frozen(&self, account_id: &AccountId) {
+        require!(!self.compliance.is_frozen(account_id), "ERR_ACCOUNT_FROZEN");
+    }
+
+    fn assert_frozen(&self, account_id: &AccountId) {
+        require!(
+            self.compliance.is_frozen(account_id),
+            "ERR_ACCOUNT_NOT_FROZEN"
+        );
+    }
+}
+
+#[near]
+impl Contract {
+    pub fn compliance_add_officer(&mut self, account_id: AccountId) {
+        self.assert_owner();
+        self.compliance.officers.insert(&account_id);
+    }
+
+    pub fn compliance_remove_officer(&mut self, account_id: AccountId) {
+        self.assert_owner();
+        self.compliance.officers.remove(&account_id);
+    }
+
+    #[payable]
+    pub fn compliance_freeze(&mut self, account_id: AccountId, reason: String) {
+        assert_one_yocto();
+        self.assert_compliance_officer();
+        require!(
+            account_id != env::current_account_id(),
+            "ERR_CANNOT_FREEZE_CONTRACT"
+        );
+        require!(
+            !self.compliance.is_frozen(&account_id),
+            "ERR_ACCOUNT_ALREADY_FROZEN"
+        );
+        require!(!reason.is_empty(), "ERR_EMPTY_REASON");
+
+        let officer_id = env::predecessor_account_id();
+        self.compliance.frozen.insert(
+            &account_id,
+            &Freeze {
+                officer_id: officer_id.clone(),
+                reason: reason.clone(),
+                frozen_at: env::block_timestamp().into(),
+            },
+        );
+        Event::AccountFrozen {
+            account_id: &account_id,
+            officer_id: &officer_id,
+            reason: &reason,
+        }
+        .emit();
+    }
+
+    pub fn compliance_unfreeze(&mut self, account_id: AccountId, reason: String) {
+        self.assert_compliance_officer();
+        self.assert_frozen(&account_id);
+        require!(!reason.is_empty(), "ERR_EMPTY_REASON");
+
+        self.compliance.frozen.remove(&account_id);
+
+        Event::AccountUnfrozen {
+            account_id: &account_id,
+            officer_id: &env::predecessor_account_id(),
+            reason: &reason,
+        }
+        .emit();
+    }
+
+    /// Schedules the transfer of `amount` tokens of the frozen account `from` to `to`, executable
+    /// once the timelock has passed.
+    #[payable]
+    pub fn compliance_seize(
+        &mut self,
+        from: AccountId,
+        to: AccountId,
+        amount: U128,
+        case_ref: String,
+    ) -> u64 {
+        assert_one_yocto();
+        self.assert_compliance_officer();
+        self.assert_frozen(&from);
+        require!(from != to, "ERR_SEIZURE_TO_SELF");
+        require!(amount.0 > 0, "ERR_ZERO_AMOUNT");
+        require!(!case_ref.is_empty(), "ERR_EMPTY_CASE_REF");
+
+        let now = env::block_timestamp();
+        let seizure = Seizure {
+            id: self.compliance.seizures.len(),
+            from,
+            to,
+            amount,
+            case_ref,
+            officer_id: env::predecessor_account_id(),
+            scheduled_at: now.into(),
+            executable_at: (now + SEIZURE_TIMELOCK).into(),
+            status: SeizureStatus::Scheduled,
+        };
+        self.compliance.seizures.push(&seizure);
+        Event::SeizureScheduled {
+            seizure_id: seizure.id,
+            from: &seizure.from,
+            to: &seizure.to,
+            amount,
+            case_ref: &seizure.case_ref,
+            executable_at: seizure.executable_at,
+        }
+        .emit();
+
+        seizure.id
+    }
+
+    /// Moves the seized tokens once the timelock has passed, provided the account is still
+    /// frozen.
+    #[payable]
+    pub fn compliance_execute_seizure(&mut self, seizure_id: u64) {
+        assert_one_yocto();
+        self.assert_compliance_officer();
+        let mut seizure = self.compliance.scheduled_seizure(seizure_id);
+        require!(
+            env::block_timestamp() >= seizure.executable_at.0,
+            "ERR_SEIZURE_TIMELOCK_NOT_OVER"
+        );
+        self.assert_frozen(&seizure.from);
+
+        self.token.internal_transfer(
+            &seizure.from,
+            &seizure.to,
+            seizure.amount.0,
+            Some(format!("seizure {}", seizure.case_ref)),
+        );
+        self.on_balance_change(Some(&seizure.from), Some(&seizure.to), seizure.amount.0);
+
+        seizure.status = SeizureStatus::Executed;
+        self.compliance.seizures.replace(seizure_id, &seizure);
+        Event::FundsSeized {
+            seizure_id,
+            from: &seizure.from,
+            to: &seizure.to,
+            amount: seizure.amount,
+            case_ref: &seizure.case_ref,
+        }
+        .emit();
+    }
+
+    /// Cancels a scheduled seizure. Can be called by an officer or the owner.
+    #[payable]
+    pub fn compliance_cancel_seizure(&mut self, seizure_id: u64) {
+        assert_one_yocto();
+        if !self
+            .compliance
+            .officers
+            .contains(&env::predecessor_account_id())
+        {
+            self.assert_owner();
+        }
+        let mut seizure = self.compliance.scheduled_seizure(seizure_id);
+
+        seizure.status = SeizureStatus::Canceled;
+        self.compliance.seizures.replace(seizure_id, &seizure);
+        Event::SeizureCanceled {
+            seizure_id,
+            canceled_by: &env::predecessor_account_id(),
+        }
+        .emit();
+    }
+
+    pub fn compliance_seizure(&self, seizure_id: u64) -> Option<Seizure> {
+        self.compliance.seizures.get(seizure_id)
+    }
+
+    /// Lists all seizures, whatever their status, in the order they were scheduled.
+    pub fn compliance_seizures(&self, from_index: Option<u64>, limit: Option<u64>) -> Vec<Seizure> {
+        self.compliance
+            .seizures
+            .iter()
+            .skip(from_index.unwrap_or(0) as usize)
+            .take(limit.unwrap_or(u64::MAX) as usize)
+            .collect()
+    }
+
+    pub fn compliance_officers(
+        &self,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> Vec<AccountId> {
+        self.compliance
+            .officers
+            .iter()
+            .skip(from_index.unwrap_or(0) as usize)
+            .take(limit.unwrap_or(u64::MAX) as usize)
+            .collect()
+    }
+
+    pub fn compliance_freeze_of(&self, account_id: AccountId) -> Option<Freeze> {
+        self.compliance.frozen.get(&account_id)
+    }
+
+    pub fn compliance_frozen_accounts(
+        &self,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> Vec<(AccountId, Freeze)> {
+        self.compliance
+            .frozen
+            .iter()
+            .skip(from_index.unwrap_or(0) as usize)
+            .take(limit.unwrap_or(u64::MAX) as usize)
+            .collect()
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_contract_standards::fungible_token::FungibleTokenCore;
+    use near_contract_standards::storage_management::StorageManagement;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, NearToken};
+
+    use super::*;
+    use crate::test_utils::*;
+
+    fn officer() -> AccountId {
+        accounts(4)
+    }
+
+    fn setup_frozen(account_id: AccountId) -> (Contract, VMContextBuilder) {
+        let (mut contract, mut context) = setup();
+        register(&mut contract, &mut context, user1());
+        transfer(&mut contract, &mut context, owner(), user1(), 1_000);
+
+        testing_env!(context.predecessor_account_id(owner()).build());
+        contract.compliance_add_officer(officer());
+        testing_env!(context
+            .predecessor_account_id(officer())
+            .attached_deposit(ONE_YOCTO)
+            .build());
+        contract.compliance_freeze(account_id, "exploit".to_string());
+
+        (contract, context)
+    }
+
+    #[test]
+    fn test_freeze_and_unfreeze() {
+        let (mut contract, mut context) = setup_frozen(user1());
+
+        let freeze = contract.compliance_freeze_of(user1()).unwrap();
+        assert_eq!(freeze.officer_id, officer());
+        assert_eq!(freeze.reason, "exploit");
+        assert_eq!(contract.compliance_frozen_accounts(None, None).len(), 1);
+
+        contract.compliance_unfreeze(user1(), "cleared".to_string());
+        assert!(contract.compliance_freeze_of(user1()).is_none());
+
+        transfer(&mut contract, &mut context, user1(), owner(), 1_000);
+        assert_eq!(contract.ft_balance_of(user1()).0, 0);
+    }
+
+    #[should_panic(expected = "ERR_EMPTY_REASON")]
+    #[test]
+    fn test_unfreeze_panics_without_reason() {
+        let (mut contract, _) = setup_frozen(user1());
+
+        contract.compliance_unfreeze(user1(), String::new());
+    }
+
+    #[should_panic(expected = "ERR_ACCOUNT_FROZEN")]
+    #[test]
+    fn test_frozen_account_cannot_send() {
+        let (mut contract, mut context) = setup_frozen(user1());
+
+        transfer(&mut contract, &mut context, user1(), owner(), 1);
+    }
+
+    #[should_panic(expected = "ERR_ACCOUNT_FROZEN")]
+    #[test]
+    fn test_frozen_account_cannot_receive() {
+        let (mut contract, mut context) = setup_frozen(user1());
+
+        transfer(&mut contract, &mut context, owner(), user1(), 1);
+    }
+
+    #[should_panic(expected = "ERR_ACCOUNT_FROZEN")]
+    #[test]
+    fn test_frozen_account_cannot_transfer_call() {
+        let (mut contract, mut context) = setup_frozen(user1());
+
+        testing_env!(context
+            .predecessor_account_id(user1())
+            .attached_deposit(ONE_YOCTO)
+            .build());
+        let _ = contract.ft_transfer_call(owner(), 1.into(), None, String::new());
+    }
+
+    #[should_panic(expected = "ERR_ACCOUNT_FROZEN")]
+    #[test]
+    fn test_frozen_account_cannot_force_unregister() {
+        let (mut contract, mut context) = setup_frozen(user1());
+
+        testing_env!(context
+            .predecessor_account_id(user1())
+            .attached_deposit(ONE_YOCTO)
+            .build());
+        contract.storage_unregister(Some(true));
+    }
+
+    #[test]
+    fn test_seizure_after_timelock() {
+        let (mut contract, mut context) = setup_frozen(user1());
+
+        let seizure_id = contract.compliance_seize(user1(), owner(), 600.into(), "case-1".into());
+        testing_env!(context.block_timestamp(SEIZURE_TIMELOCK).build());
+        contract.compliance_execute_seizure(seizure_id);
+
+        assert_eq!(contract.ft_balance_of(user1()).0, 400);
+        assert_eq!(contract.ft_balance_of(owner()).0, TOTAL_SUPPLY - 400);
+        let seizures = contract.compliance_seizures(None, None);
+        assert_eq!(seizures.len(), 1);
+        assert_eq!(seizures[0].status, SeizureStatus::Executed);
+        assert_eq!(seizures[0].case_ref, "case-1");
+    }
+
+    #[should_panic(expected = "ERR_SEIZURE_TIMELOCK_NOT_OVER")]
+    #[test]
+    fn test_seizure_panics_before_timelock() {
+        let (mut contract, mut context) = setup_frozen(user1());
+
+        let seizure_id = contract.compliance_seize(user1(), owner(), 600.into(), "case-1".into());
+        testing_env!(context.block_timestamp(SEIZURE_TIMELOCK - 1).build());
+        contract.compliance_execute_seizure(seizure_id);
+    }
+
+    #[should_panic(expected = "ERR_ACCOUNT_NOT_FROZEN")]
+    #[test]
+    fn test_seizure_panics_once_unfrozen() {
+        let (mut contract, mut context) = setup_frozen(user1());
+
+        let seizure_id = contract.compliance_seize(user1(), owner(), 600.into(), "case-1".into());
+        contract.compliance_unfreeze(user1(), "cleared".to_string());
+        testing_env!(context.block_timestamp(SEIZURE_TIMELOCK).build());
+        contract.compliance_execute_seizure(seizure_id);
+    }
+
+    #[should_panic(expected = "ERR_SEIZURE_NOT_SCHEDULED")]
+    #[test]
+    fn test_canceled_seizure_cannot_execute() {
+        let (mut contract, mut context) = setup_frozen(user1());
+
+        let seizure_id = contract.compliance_seize(user1(), owner(), 600.into(), "case-1".into());
+        testing_env!(context.predecessor_account_id(owner()).build());
+        contract.compliance_cancel_seizure(seizure_id);
+        assert_eq!(
+            contract.compliance_seizure(seizure_id).unwrap().status,
+            SeizureStatus::Canceled
+        );
+
+        testing_env!(context
+            .predecessor_account_id(officer())
+            .block_timestamp(SEIZURE_TIMELOCK)
+            .build());
+        contract.compliance_execute_seizure(seizure_id);
+    }
+
+    #[should_panic(expected = "ERR_NOT_COMPLIANCE_OFFICER")]
+    #[test]
+    fn test_freeze_panics_for_owner() {
+        let (mut contract, mut context) = setup();
+
+        testing_env!(context
+            .predecessor_account_id(owner())
+            .attached_deposit(ONE_YOCTO)
+            .build());
+        contract.compliance_freeze(user1(), "exploit".to_string());
+    }
+
+    #[should_panic(expected = "ERR_CANNOT_FREEZE_CONTRACT")]
+    #[test]
+    fn test_freeze_panics_for_contract_account() {
+        let (mut contract, mut context) = setup();
+
+        freeze(&mut contract, &mut context, current());
+    }
+
+    #[should_panic(expected = "Requires attached deposit of exactly 1 yoctoNEAR")]
+    #[test]
+    fn test_seize_requires_one_yocto() {
+        let (mut contract, mut context) = setup_frozen(user1());
+
+        testing_env!(context
+            .attached_deposit(NearToken::from_yoctonear(0))
+            .build());
+        contract.compliance_seize(user1(), owner(), 600.into(), "case-1".into());
+    }
+}
@@ -0,0 +1,391 @@
+//! Escrowed payments with an arbiter.
+//!
+//! The depositor locks tokens for a beneficiary until they release them. If they never do, the
+//! depositor gets the tokens back once the deadline has passed, unless one of the parties opened
+//! a dispute before it: the arbiter then decides how the tokens are split. The depositor pays for
+//! the storage of the escrow and is refunded once it is settled.
+use near_contract_standards::fungible_token::Balance;
+use near_sdk::collections::LookupMap;
+use near_sdk::json_types::{U128, U64};
+use near_sdk::{assert_one_yocto, env, near, require, AccountId};
+
+use crate::account_index::AccountIndex;
+use crate::events::Event;
+use crate::internal::{assert_at_least_one_yocto, refund_deposit, refund_released_storage};
+use crate::locks::LockBucket;
+use crate::{Contract, ContractExt, StorageKey};
+
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Escrow {
+    pub id: u64,
+    pub depositor_id: AccountId,
+    pub beneficiary_id: AccountId,
+    pub arbiter_id: AccountId,
+    pub amount: U128,
+    /// Block timestamp in nanoseconds after which the depositor can take the tokens back.
+    pub deadline: U64,
+    pub disputed: bool,
+}
+
+#[near]
+pub struct Escrows {
+    escrows: LookupMap<u64, Escrow>,
+    by_account: AccountIndex,
+    next_id: u64,
+}
+
+impl Escrows {
+    pub fn new() -> Self {
+        Self {
+            escrows: LookupMap::new(StorageKey::Escrows),
+            by_account: AccountIndex::new(StorageKey::EscrowsByAccount),
+            next_id: 0,
+        }
+    }
+
+    fn escrow(&self, escrow_id: u64) -> Escrow {
+        self.escrows
+            .get(&escrow_id)
+            .unwrap_or_else(|| env::panic_str("ERR_ESCROW_NOT_FOUND"))
+    }
+
+    fn remove(&mut self, escrow: &Escrow) {
+        self.escrows.remove(&escrow.id);
+        self.by_account.remove(&escrow.depositor_id, escrow.id);
+        self.by_account.remove(&escrow.beneficiary_id, escrow.id);
+        self.by_account.remove(&escrow.arbiter_id, escrow.id);
+    }
+}
+
+impl Contract {
+    /// Removes the escrow and pays out the locked tokens.
+    fn internal_settle_escrow(
+        &mut self,
+        escrow: &Escrow,
+        beneficiary_amount: Balance,
+        depositor_amount: Balance,
+    ) {
+        let initial_storage_usage = env::storage_usage();
+        self.escrows.remove(escrow);
+        self.internal_unlock(
+            LockBucket::Escrow,
+            &escrow.depositor_id,
+            &escrow.beneficiary_id,
+            beneficiary_amount,
+            "escrow release",
+        );
+        self.internal_unlock(
+            LockBucket::Escrow,
+            &escrow.depositor_id,
+            &escrow.depositor_id,
+            depositor_amount,
+            "escrow refund",
+        );
+        refund_released_storage(&escrow.depositor_id, initial_storage_usage);
+    }
+}
+
+#[near]
+impl Contract {
+    /// Locks `amount` tokens of the predecessor for `beneficiary_id`. The attached deposit pays
+    /// for the escrow storage.
+    #[payable]
+    pub fn escrow_create(
+        &mut self,
+        beneficiary_id: AccountId,
+        amount: U128,
+        arbiter_id: AccountId,
+        deadline: U64,
+    ) -> u64 {
+        assert_at_least_one_yocto();
+        let initial_storage_usage = env::storage_usage();
+        let depositor_id = env::predecessor_account_id();
+        require!(depositor_id != beneficiary_id, "ERR_ESCROW_TO_SELF");
+        require!(
+            arbiter_id != depositor_id && arbiter_id != beneficiary_id,
+            "ERR_ARBITER_IS_A_PARTY"
+        );
+        require!(amount.0 > 0, "ERR_ZERO_AMOUNT");
+        require!(
+            deadline.0 > env::block_timestamp(),
+            "ERR_DEADLINE_IN_THE_PAST"
+        );
+
+        self.internal_lock(LockBucket::Escrow, &depositor_id, amount.0, "escrow");
+
+        let id = self.escrows.next_id;
+        self.escrows.next_id += 1;
+        let escrow = Escrow {
+            id,
+            depositor_id,
+            beneficiary_id,
+            arbiter_id,
+            amount,
+            deadline,
+            disputed: false,
+        };
+        self.escrows.escrows.insert(&id, &escrow);
+        self.escrows.by_account.insert(&escrow.depositor_id, id);
+        self.escrows.by_account.insert(&escrow.beneficiary_id, id);
+        self.escrows.by_account.insert(&escrow.arbiter_id, id);
+
+        Event::EscrowCreated {
+            escrow_id: id,
+            depositor_id: &escrow.depositor_id,
+            beneficiary_id: &escrow.beneficiary_id,
+            arbiter_id: &escrow.arbiter_id,
+            amount,
+            deadline,
+        }
+        .emit();
+
+        refund_deposit(initial_storage_usage);
+        id
+    }
+
+    /// Pays the escrowed tokens to the beneficiary. Only the depositor can release them.
+    #[payable]
+    pub fn escrow_release(&mut self, escrow_id: u64) {
+        assert_one_yocto();
+        let escrow = self.escrows.escrow(escrow_id);
+        require!(
+            env::predecessor_account_id() == escrow.depositor_id,
+            "ERR_NOT_ESCROW_DEPOSITOR"
+        );
+
+        self.internal_settle_escrow(&escrow, escrow.amount.0, 0);
+        Event::EscrowReleased {
+            escrow_id,
+            beneficiary_id: &escrow.beneficiary_id,
+            amount: escrow.amount,
+        }
+        .emit();
+    }
+
+    /// Returns the escrowed tokens to the depositor once the deadline has passed without a
+    /// dispute. Can be called by anyone.
+    #[payable]
+    pub fn escrow_refund(&mut self, escrow_id: u64) {
+        assert_one_yocto();
+        let escrow = self.escrows.escrow(escrow_id);
+        require!(
+            env::block_timestamp() >= escrow.deadline.0,
+            "ERR_DEADLINE_NOT_REACHED"
+        );
+        require!(!escrow.disputed, "ERR_ESCROW_DISPUTED");
+
+        self.internal_settle_escrow(&escrow, 0, escrow.amount.0);
+        Event::EscrowRefunded {
+            escrow_id,
+            depositor_id: &escrow.depositor_id,
+            amount: escrow.amount,
+        }
+        .emit();
+    }
+
+    /// Hands the escrow over to the arbiter. Either party can open a dispute before the deadline.
+    #[payable]
+    pub fn escrow_dispute(&mut self, escrow_id: u64) {
+        assert_one_yocto();
+        let mut escrow = self.escrows.escrow(escrow_id);
+        let account_id = env::predecessor_account_id();
+        require!(
+            account_id == escrow.depositor_id || account_id == escrow.beneficiary_id,
+            "ERR_NOT_ESCROW_PARTY"
+        );
+        require!(
+            env::block_timestamp() < escrow.deadline.0,
+            "ERR_DEADLINE_PASSED"
+        );
+        require!(!escrow.disputed, "ERR_ESCROW_DISPUTED");
+
+        escrow.disputed = true;
+        self.escrows.escrows.insert(&escrow_id, &escrow);
+        Event::EscrowDisputed {
+            escrow_id,
+            account_id: &account_id,
+        }
+        .emit();
+    }
+
+    /// Settles a disputed escrow: `beneficiary_amount` goes to the beneficiary and the rest back
+    /// to the depositor. Only the arbiter can resolve it.
+    #[payable]
+    pub fn escrow_resolve(&mut self, escrow_id: u64, beneficiary_amount: U128) {
+        assert_one_yocto();
+        let escrow = self.escrows.escrow(escrow_id);
+        require!(
+            env::predecessor_account_id() == escrow.arbiter_id,
+            "ERR_NOT_ESCROW_ARBITER"
+        );
+        require!(escrow.disputed, "ERR_ESCROW_NOT_DISPUTED");
+        require!(
+            beneficiary_amount.0 <= escrow.amount.0,
+            "ERR_AMOUNT_EXCEEDS_ESCROW"
+        );
+
+        let depositor_amount = escrow.amount.0 - beneficiary_amount.0;
+        self.internal_settle_escrow(&escrow, beneficiary_amount.0, depositor_amount);
+        Event::EscrowResolved {
+            escrow_id,
+            beneficiary_amount,
+            depositor_amount: depositor_amount.into(),
+        }
+        .emit();
+    }
+
+    pub fn escrow(&self, escrow_id: u64) -> Option<Escrow> {
+        self.escrows.escrows.get(&escrow_id)
+    }
+
+    /// Lists the open escrows `account_id` is the depositor, beneficiary or arbiter of.
+    pub fn escrows_of(
+        &self,
+        account_id: AccountId,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> Vec<Escrow> {
+        self.escrows
+            .by_account
+            .ids(&account_id, from_index, limit)
+            .into_iter()
+            .filter_map(|id| self.escrows.escrows.get(&id))
+            .collect()
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_contract_standards::fungible_token::FungibleTokenCore;
+    use near_sdk::test_utils::VMContextBuilder;
+    use near_sdk::{testing_env, Timestamp};
+
+    use super::*;
+    use crate::test_utils::*;
+
+    const AMOUNT: Balance = 1_000;
+    const DEADLINE: Timestamp = 1_000;
+
+    fn setup_escrow() -> (Contract, VMContextBuilder) {
+        let (mut contract, mut context) = setup();
+        register(&mut contract, &mut context, user1());
+
+        testing_env!(context
+            .block_timestamp(0)
+            .predecessor_account_id(owner())
+            .attached_deposit(ONE_NEAR)
+            .build());
+        contract.escrow_create(user1(), AMOUNT.into(), user2(), DEADLINE.into());
+
+        (contract, context)
+    }
+
+    #[test]
+    fn test_create_excludes_funds_from_balance() {
+        let (contract, _) = setup_escrow();
+
+        assert_eq!(contract.ft_balance_of(owner()).0, TOTAL_SUPPLY - AMOUNT);
+        assert_eq!(contract.locks.bucket(LockBucket::Escrow), AMOUNT);
+        for account_id in [owner(), user1(), user2()] {
+            assert_eq!(contract.escrows_of(account_id, None, None).len(), 1);
+        }
+    }
+
+    #[test]
+    fn test_release_pays_beneficiary() {
+        let (mut contract, mut context) = setup_escrow();
+
+        at(&mut context, 10, owner());
+        contract.escrow_release(0);
+
+        assert_eq!(contract.ft_balance_of(user1()).0, AMOUNT);
+        assert!(contract.escrow(0).is_none());
+        assert!(contract.escrows_of(user2(), None, None).is_empty());
+    }
+
+    #[should_panic(expected = "ERR_ACCOUNT_FROZEN")]
+    #[test]
+    fn test_release_panics_when_depositor_frozen() {
+        let (mut contract, mut context) = setup_escrow();
+        freeze(&mut contract, &mut context, owner());
+
+        at(&mut context, 10, owner());
+        contract.escrow_release(0);
+    }
+
+    #[should_panic(expected = "ERR_ACCOUNT_FROZEN")]
+    #[test]
+    fn test_release_panics_when_beneficiary_frozen() {
+        let (mut contract, mut context) = setup_escrow();
+        freeze(&mut contract, &mut context, user1());
+
+        at(&mut context, 10, owner());
+        contract.escrow_release(0);
+    }
+
+    #[should_panic(expected = "ERR_NOT_ESCROW_DEPOSITOR")]
+    #[test]
+    fn test_release_panics_for_beneficiary() {
+        let (mut contract, mut context) = setup_escrow();
+
+        at(&mut context, 10, user1());
+        contract.escrow_release(0);
+    }
+
+    #[test]
+    fn test_refund_after_deadline() {
+        let (mut contract, mut context) = setup_escrow();
+
+        at(&mut context, DEADLINE, user1());
+        contract.escrow_refund(0);
+
+        assert_eq!(contract.ft_balance_of(owner()).0, TOTAL_SUPPLY);
+        assert_eq!(contract.locks.bucket(LockBucket::Escrow), 0);
+    }
+
+    #[should_panic(expected = "ERR_DEADLINE_NOT_REACHED")]
+    #[test]
+    fn test_refund_panics_before_deadline() {
+        let (mut contract, mut context) = setup_escrow();
+
+        at(&mut context, DEADLINE - 1, owner());
+        contract.escrow_refund(0);
+    }
+
+    #[should_panic(expected = "ERR_ESCROW_DISPUTED")]
+    #[test]
+    fn test_refund_panics_when_disputed() {
+        let (mut contract, mut context) = setup_escrow();
+
+        at(&mut context, 10, user1());
+        contract.escrow_dispute(0);
+
+        at(&mut context, DEADLINE, owner());
+        contract.escrow_refund(0);
+    }
+
+    #[test]
+    fn test_arbiter_resolves_dispute() {
+        let (mut contract, mut context) = setup_escrow();
+
+        at(&mut context, 10, user1());
+        contract.escrow_dispute(0);
+
+        at(&mut context, DEADLINE + 10, user2());
+        contract.escrow_resolve(0, 300.into());
+
+        assert_eq!(contract.ft_balance_of(user1()).0, 300);
+        assert_eq!(contract.ft_balance_of(owner()).0, TOTAL_SUPPLY - 300);
+        assert!(contract.escrow(0).is_none());
+    }
+
+    #[should_panic(expected = "ERR_ESCROW_NOT_DISPUTED")]
+    #[test]
+    fn test_resolve_panics_without_dispute() {
+        let (mut contract, mut context) = setup_escrow();
+
+        at(&mut context, 10, user2());
+        contract.escrow_resolve(0, 0.into());
+    }
+}
@@ -0,0 +1,172 @@
+//! NEP-297 events emitted by the ITLX contract on top of the NEP-141 ones.
+use near_sdk::json_types::{U128, U64};
+use near_sdk::{near, AccountId};
+
+use crate::trading::TransferMode;
+
+#[near(event_json(standard = "itlx"))]
+pub enum Event<'a> {
+    #[event_version("1.0.0")]
+    DelegateChanged {
+        delegator: &'a AccountId,
+        from_delegate: Option<&'a AccountId>,
+        to_delegate: &'a AccountId,
+    },
+    #[event_version("1.0.0")]
+    AirdropCreated {
+        campaign_id: u64,
+        total_amount: U128,
+        expiry: U64,
+    },
+    #[event_version("1.0.0")]
+    AirdropClaimed {
+        campaign_id: u64,
+        account_id: &'a AccountId,
+        amount: U128,
+    },
+    #[event_version("1.0.0")]
+    AirdropClosed {
+        campaign_id: u64,
+        treasury_id: &'a AccountId,
+        unclaimed_amount: U128,
+    },
+    #[event_version("1.0.0")]
+    StreamCreated {
+        stream_id: u64,
+        sender_id: &'a AccountId,
+        receiver_id: &'a AccountId,
+        rate_per_second: U128,
+        start: U64,
+        end: U64,
+    },
+    #[event_version("1.0.0")]
+    StreamWithdrawn {
+        stream_id: u64,
+        receiver_id: &'a AccountId,
+        amount: U128,
+    },
+    #[event_version("1.0.0")]
+    StreamCanceled {
+        stream_id: u64,
+        receiver_amount: U128,
+        sender_refund: U128,
+    },
+    #[event_version("1.0.0")]
+    EscrowCreated {
+        escrow_id: u64,
+        depositor_id: &'a AccountId,
+        beneficiary_id: &'a AccountId,
+        arbiter_id: &'a AccountId,
+        amount: U128,
+        deadline: U64,
+    },
+    #[event_version("1.0.0")]
+    EscrowReleased {
+        escrow_id: u64,
+        beneficiary_id: &'a AccountId,
+        amount: U128,
+    },
+    #[event_version("1.0.0")]
+    EscrowRefunded {
+        escrow_id: u64,
+        depositor_id: &'a AccountId,
+        amount: U128,
+    },
+    #[event_version("1.0.0")]
+    EscrowDisputed {
+        escrow_id: u64,
+        account_id: &'a AccountId,
+    },
+    #[event_version("1.0.0")]
+    EscrowResolved {
+        escrow_id: u64,
+        beneficiary_amount: U128,
+        depositor_amount: U128,
+    },
+    #[event_version("1.0.0")]
+    ChannelOpened {
+        channel_id: u64,
+        user_id: &'a AccountId,
+        provider_id: &'a AccountId,
+        amount: U128,
+    },
+    #[event_version("1.0.0")]
+    ChannelClaimed {
+        channel_id: u64,
+        provider_id: &'a AccountId,
+        amount: U128,
+        total_claimed: U128,
+    },
+    #[event_version("1.0.0")]
+    ChannelCloseStarted { channel_id: u64, closes_at: U64 },
+    #[event_version("1.0.0")]
+    ChannelClosed {
+        channel_id: u64,
+        claimed: U128,
+        refund: U128,
+    },
+    #[event_version("1.0.0")]
+    SessionOpened {
+        session_id: &'a str,
+        user_id: &'a AccountId,
+        provider_id: &'a AccountId,
+        amount: U128,
+        expires_at: U64,
+    },
+    #[event_version("1.0.0")]
+    SessionUsageReported { session_id: &'a str, used: U128 },
+    #[event_version("1.0.0")]
+    SessionSettled {
+        session_id: &'a str,
+        provider_id: &'a AccountId,
+        provider_amount: U128,
+        user_refund: U128,
+    },
+    #[event_version("1.0.0")]
+    SessionTimedOut {
+        session_id: &'a str,
+        user_id: &'a AccountId,
+        refund: U128,
+    },
+    #[event_version("1.0.0")]
+    AccountFrozen {
+        account_id: &'a AccountId,
+        officer_id: &'a AccountId,
+        reason: &'a str,
+    },
+    #[event_version("1.0.0")]
+    AccountUnfrozen {
+        account_id: &'a AccountId,
+        officer_id: &'a AccountId,
+        reason: &'a str,
+    },
+    #[event_version("1.0.0")]
+    SeizureScheduled {
+        seizure_id: u64,
+        from: &'a AccountId,
+        to: &'a AccountId,
+        amount: U128,
+        case_ref: &'a str,
+        executable_at: U64,
+    },
+    #[event_version("1.0.0")]
+    FundsSeized {
+        seizure_id: u64,
+        from: &'a AccountId,
+        to: &'a AccountId,
+        amount: U128,
+        case_ref: &'a str,
+    },
+    #[event_version("1.0.0")]
+    SeizureCanceled {
+        seizure_id: u64,
+        canceled_by: &'a AccountId,
+    },
+    #[event_version("1.0.0")]
+    TransferModeChanged { mode: TransferMode },
+    #[event_version("1.0.0")]
+    MetadataUpdated {
+        updated_by: &'a AccountId,
+        fields: Vec<&'a str>,
+    },
+}
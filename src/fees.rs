@@ -0,0 +1,509 @@
+//! Optional protocol fee on transfers.
+//!
+//! When configured, `ft_transfer` and `ft_transfer_call` deduct `bps` basis points of the amount,
+//! bounded by `min_fee` and `max_fee`, and send them to the treasury as a separate transfer with
+//! its own `ft_transfer` event. The receiver gets the rest, which is also the amount passed to
+//! `ft_on_transfer` and refunded by `ft_resolve_transfer`: the fee itself is never refunded.
+//! Transfers from or to an exempt account, or the treasury, are free. The treasury cannot
+//! unregister while the fee is configured, and the fee is checked like any other transfer to it:
+//! while the treasury is frozen, or would exceed the launch `max_balance`, fee-bearing transfers
+//! fail.
+//!
+//! Routes can get their own rate: an override applies to the transfers whose sender and receiver
+//! match its [`FeeRoute`] patterns. A pattern is either an exact account id, or a suffix starting
+//! with a dot such as `.sweat` matching all its sub-accounts; a missing pattern matches any
+//! account. When several overrides match, the most specific sender pattern wins, then the most
+//! specific receiver pattern.
+use near_contract_standards::fungible_token::Balance;
+use near_sdk::collections::{UnorderedMap, UnorderedSet};
+use near_sdk::json_types::U128;
+use near_sdk::{near, require, AccountId};
+
+use crate::{Contract, ContractExt, StorageKey};
+
+const MAX_BASIS_POINTS: u16 = 10_000;
+
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct FeeConfig {
+    pub bps: u16,
+    pub min_fee: U128,
+    pub max_fee: Option<U128>,
+    pub treasury_id: AccountId,
+}
+
+/// Sender and receiver patterns a [`FeeOverride`] applies to.
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct FeeRoute {
+    pub sender: Option<String>,
+    pub receiver: Option<String>,
+}
+
+/// Rate replacing the one of the [`FeeConfig`] on a route. A zero rate exempts the route.
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct FeeOverride {
+    pub bps: u16,
+    pub min_fee: U128,
+    pub max_fee: Option<U128>,
+}
+
+fn fee_amount(amount: Balance, bps: u16, min_fee: U128, max_fee: Option<U128>) -> Balance {
+    // split so that `amount * bps` cannot overflow
+    let fee = amount / Balance::from(MAX_BASIS_POINTS) * Balance::from(bps)
+        + amount % Balance::from(MAX_BASIS_POINTS) * Balance::from(bps)
+            / Balance::from(MAX_BASIS_POINTS);
+    let fee = std::cmp::max(fee, min_fee.0);
+    max_fee.map_or(fee, |max_fee| std::cmp::min(fee, max_fee.0))
+}
+
+fn assert_valid_rate(bps: u16, min_fee: U128, max_fee: Option<U128>) {
+    require!(bps <= MAX_BASIS_POINTS, "ERR_INVALID_FEE_BPS");
+    require!(
+        max_fee.is_none_or(|max_fee| max_fee.0 >= min_fee.0),
+        "ERR_MAX_FEE_BELOW_MIN_FEE"
+    );
+}
+
+fn assert_valid_pattern(pattern: &Option<String>) {
+    if let Some(pattern) = pattern {
+        let account_id = pattern.strip_prefix('.').unwrap_or(pattern);
+        require!(
+            account_id.parse::<AccountId>().is_ok(),
+            "ERR_INVALID_ACCOUNT_PATTERN"
+        );
+    }
+}
+
+/// Patterns matching `account_id`, from the most specific to the catch-all.
+fn patterns_of(account_id: &AccountId) -> Vec<Option<String>> {
+    let account_id = account_id.as_str();
+    let mut patterns = vec![Some(account_id.to_string())];
+    patterns.extend(
+        account_id
+            .match_indices('.')
+            .map(|(index, _)| Some(account_id[index..].to_string())),
+    );
+    patterns.push(None);
+    patterns
+}
+
+#[near]
+pub struct Fees {
+    config: Option<FeeConfig>,
+    exempt: UnorderedSet<AccountId>,
+    overrides: UnorderedMap<FeeRoute, FeeOverride>,
+}
+
+impl Fees {
+    pub fn new() -> Self {
+        Self {
+            config: None,
+            exempt: UnorderedSet::new(StorageKey::FeeExempt),
+            overrides: UnorderedMap::new(StorageKey::FeeOverrides),
+        }
+    }
+
+    pub fn is_treasury(&self, account_id: &AccountId) -> bool {
+        self.config
+            .as_ref()
+            .is_some_and(|config| config.treasury_id == *account_id)
+    }
+
+    /// Fee owed on a transfer of `amount` from `sender_id` to `receiver_id`.
+    pub fn transfer_fee(
+        &self,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+        amount: Balance,
+    ) -> Balance {
+        match self.config.as_ref() {
+            Some(config)
+                if *sender_id != config.treasury_id
+                    && *receiver_id != config.treasury_id
+                    && !self.exempt.contains(sender_id)
+                    && !self.exempt.contains(receiver_id) =>
+            {
+                match self.route_override(sender_id, receiver_id) {
+                    Some(fee) => fee_amount(amount, fee.bps, fee.min_fee, fee.max_fee),
+                    None => fee_amount(amount, config.bps, config.min_fee, config.max_fee),
+                }
+            }
+            _ => 0,
+        }
+    }
+
+    fn route_override(
+        &self,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+    ) -> Option<FeeOverride> {
+        if self.overrides.is_empty() {
+            return None;
+        }
+        let receivers = patterns_of(receiver_id);
+        patterns_of(sender_id).into_iter().find_map(|sender| {
+            receivers.iter().find_map(|receiver| {
+                self.overrides.get(&FeeRoute {
+                    sender: sender.clone(),
+                    receiver: receiver.clone(),
+                })
+            })
+        })
+    }
+}
+
+impl Contract {
+    /// Sends the fee on a transfer of `amount` to the treasury and returns what is left for the
+    /// receiver.
+    pub(crate) fn internal_charge_transfer_fee(
+        &mut self,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+        amount: Balance,
+    ) -> Balance {
+        let fee = self.fees.transfer_fee(sender_id, receiver_id, amount);
+        if fee == 0 {
+            return amount;
+        }
+        require!(fee < amount, "ERR_AMOUNT_DOES_NOT_COVER_TRANSFER_FEE");
+
+        let treasury_id = self.fees.config.as_ref().unwrap().treasury_id.clone();
+        self.assert_not_frozen(&treasury_id);
+        self.assert_transfer_mode_allows(sender_id, &treasury_id);
+        self.assert_within_launch_limits(sender_id, &treasury_id, fee);
+        self.token.internal_transfer(
+            sender_id,
+            &treasury_id,
+            fee,
+            Some("transfer fee".to_string()),
+        );
+        self.on_balance_change(Some(sender_id), Some(&treasury_id), fee);
+        self.internal_record_transfer_stats(sender_id, &treasury_id, fee);
+        amount - fee
+    }
+}
+
+#[near]
+impl Contract {
+    /// Sets the transfer fee, or disables it when `config` is `None`. The treasury must be
+    /// registered.
+    pub fn fees_set_config(&mut self, config: Option<FeeConfig>) {
+        self.assert_owner();
+        if let Some(config) = config.as_ref() {
+            assert_valid_rate(config.bps, config.min_fee, config.max_fee);
+            require!(
+                self.token.accounts.contains_key(&config.treasury_id),
+                "ERR_TREASURY_NOT_REGISTERED"
+            );
+        }
+        self.fees.config = config;
+    }
+
+    pub fn fees_add_exempt_accounts(&mut self, account_ids: Vec<AccountId>) {
+        self.assert_owner();
+        for account_id in account_ids.iter() {
+            self.fees.exempt.insert(account_id);
+        }
+    }
+
+    pub fn fees_remove_exempt_accounts(&mut self, account_ids: Vec<AccountId>) {
+        self.assert_owner();
+        for account_id in account_ids.iter() {
+            self.fees.exempt.remove(account_id);
+        }
+    }
+
+    /// Sets the rate of `route`, or removes its override when `fee` is `None`.
+    pub fn fees_set_route_override(&mut self, route: FeeRoute, fee: Option<FeeOverride>) {
+        self.assert_owner();
+        assert_valid_pattern(&route.sender);
+        assert_valid_pattern(&route.receiver);
+        match fee {
+            Some(fee) => {
+                assert_valid_rate(fee.bps, fee.min_fee, fee.max_fee);
+                self.fees.overrides.insert(&route, &fee);
+            }
+            None => {
+                self.fees.overrides.remove(&route);
+            }
+        }
+    }
+
+    pub fn fees_route_overrides(
+        &self,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> Vec<(FeeRoute, FeeOverride)> {
+        self.fees
+            .overrides
+            .iter()
+            .skip(from_index.unwrap_or(0) as usize)
+            .take(limit.unwrap_or(u64::MAX) as usize)
+            .collect()
+    }
+
+    /// Override applying to transfers from `sender_id` to `receiver_id`, if any.
+    pub fn fees_route_override(
+        &self,
+        sender_id: AccountId,
+        receiver_id: AccountId,
+    ) -> Option<FeeOverride> {
+        self.fees.route_override(&sender_id, &receiver_id)
+    }
+
+    pub fn fees_config(&self) -> Option<FeeConfig> {
+        self.fees.config.clone()
+    }
+
+    pub fn fees_exempt_accounts(
+        &self,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> Vec<AccountId> {
+        self.fees
+            .exempt
+            .iter()
+            .skip(from_index.unwrap_or(0) as usize)
+            .take(limit.unwrap_or(u64::MAX) as usize)
+            .collect()
+    }
+
+    /// Fee a transfer of `amount` from `sender_id` to `receiver_id` would pay.
+    pub fn ft_transfer_fee(
+        &self,
+        sender_id: AccountId,
+        receiver_id: AccountId,
+        amount: U128,
+    ) -> U128 {
+        self.fees
+            .transfer_fee(&sender_id, &receiver_id, amount.0)
+            .into()
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_contract_standards::fungible_token::{FungibleTokenCore, FungibleTokenResolver};
+    use near_contract_standards::storage_management::StorageManagement;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, PromiseResult};
+
+    use super::*;
+    use crate::test_utils::*;
+
+    fn treasury() -> AccountId {
+        accounts(4)
+    }
+
+    fn setup_fee(min_fee: Balance, max_fee: Option<Balance>) -> (Contract, VMContextBuilder) {
+        let (mut contract, mut context) = setup();
+        register(&mut contract, &mut context, user1());
+        register(&mut contract, &mut context, treasury());
+
+        testing_env!(context.predecessor_account_id(owner()).build());
+        contract.fees_set_config(Some(FeeConfig {
+            bps: 100,
+            min_fee: min_fee.into(),
+            max_fee: max_fee.map(U128),
+            treasury_id: treasury(),
+        }));
+
+        (contract, context)
+    }
+
+    #[test]
+    fn test_fee_bounds() {
+        let (contract, _) = setup_fee(5, Some(50));
+
+        assert_eq!(
+            contract.ft_transfer_fee(owner(), user1(), 1_000.into()).0,
+            10
+        );
+        assert_eq!(contract.ft_transfer_fee(owner(), user1(), 100.into()).0, 5);
+        assert_eq!(
+            contract.ft_transfer_fee(owner(), user1(), 100_000.into()).0,
+            50
+        );
+    }
+
+    #[test]
+    fn test_transfer_sends_fee_to_treasury() {
+        let (mut contract, mut context) = setup_fee(0, None);
+
+        transfer(&mut contract, &mut context, owner(), user1(), 1_000);
+
+        assert_eq!(contract.ft_balance_of(user1()).0, 990);
+        assert_eq!(contract.ft_balance_of(treasury()).0, 10);
+        assert_eq!(contract.ft_balance_of(owner()).0, TOTAL_SUPPLY - 1_000);
+    }
+
+    #[test]
+    fn test_exempt_accounts_pay_no_fee() {
+        let (mut contract, mut context) = setup_fee(0, None);
+
+        testing_env!(context.predecessor_account_id(owner()).build());
+        contract.fees_add_exempt_accounts(vec![user1()]);
+        assert_eq!(contract.fees_exempt_accounts(None, None), vec![user1()]);
+
+        transfer(&mut contract, &mut context, owner(), user1(), 1_000);
+        assert_eq!(contract.ft_balance_of(user1()).0, 1_000);
+        assert_eq!(contract.ft_balance_of(treasury()).0, 0);
+    }
+
+    #[test]
+    fn test_transfer_call_refund_excludes_fee() {
+        let (mut contract, mut context) = setup_fee(0, None);
+
+        testing_env!(context
+            .predecessor_account_id(owner())
+            .attached_deposit(ONE_YOCTO)
+            .build());
+        let _ = contract.ft_transfer_call(user1(), 1_000.into(), None, String::new());
+        assert_eq!(contract.ft_balance_of(user1()).0, 990);
+
+        // the receiver used nothing: the net amount goes back, the fee stays with the treasury
+        testing_env!(
+            context.predecessor_account_id(current()).build(),
+            near_sdk::test_vm_config(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Successful(
+                near_sdk::serde_json::to_vec(&U128(990)).unwrap()
+            )],
+        );
+        assert_eq!(
+            contract.ft_resolve_transfer(owner(), user1(), 990.into()).0,
+            0
+        );
+        assert_eq!(contract.ft_balance_of(owner()).0, TOTAL_SUPPLY - 10);
+        assert_eq!(contract.ft_balance_of(treasury()).0, 10);
+    }
+
+    fn set_override(
+        contract: &mut Contract,
+        sender: Option<&str>,
+        receiver: Option<&str>,
+        bps: u16,
+    ) {
+        contract.fees_set_route_override(
+            FeeRoute {
+                sender: sender.map(str::to_string),
+                receiver: receiver.map(str::to_string),
+            },
+            Some(FeeOverride {
+                bps,
+                min_fee: 0.into(),
+                max_fee: None,
+            }),
+        );
+    }
+
+    #[test]
+    fn test_route_overrides_prefer_specific_patterns() {
+        let (mut contract, mut context) = setup_fee(0, None);
+        let exchange: AccountId = "hot.exchange.near".parse().unwrap();
+
+        testing_env!(context.predecessor_account_id(owner()).build());
+        set_override(&mut contract, None, Some(".exchange.near"), 10);
+        set_override(&mut contract, None, Some("hot.exchange.near"), 0);
+        set_override(&mut contract, Some("bob"), Some(".near"), 50);
+        assert_eq!(contract.fees_route_overrides(None, None).len(), 3);
+
+        let fee = |contract: &Contract, sender: AccountId, receiver: AccountId| {
+            contract.ft_transfer_fee(sender, receiver, 10_000.into()).0
+        };
+        assert_eq!(
+            fee(&contract, user1(), "cold.exchange.near".parse().unwrap()),
+            10
+        );
+        assert_eq!(fee(&contract, user1(), exchange.clone()), 0);
+        // the sender pattern is matched first
+        assert_eq!(fee(&contract, owner(), exchange), 50);
+        assert_eq!(fee(&contract, user1(), user2()), 100);
+
+        contract.fees_set_route_override(
+            FeeRoute {
+                sender: Some("bob".to_string()),
+                receiver: Some(".near".to_string()),
+            },
+            None,
+        );
+        assert_eq!(
+            fee(&contract, owner(), "hot.exchange.near".parse().unwrap()),
+            0
+        );
+    }
+
+    #[should_panic(expected = "ERR_INVALID_ACCOUNT_PATTERN")]
+    #[test]
+    fn test_route_override_panics_on_invalid_pattern() {
+        let (mut contract, mut context) = setup_fee(0, None);
+
+        testing_env!(context.predecessor_account_id(owner()).build());
+        set_override(&mut contract, Some("..near"), None, 10);
+    }
+
+    #[should_panic(expected = "ERR_AMOUNT_DOES_NOT_COVER_TRANSFER_FEE")]
+    #[test]
+    fn test_transfer_panics_below_min_fee() {
+        let (mut contract, mut context) = setup_fee(100, None);
+
+        transfer(&mut contract, &mut context, owner(), user1(), 100);
+    }
+
+    #[should_panic(expected = "ERR_NOT_OWNER")]
+    #[test]
+    fn test_set_config_panics_for_non_owner() {
+        let (mut contract, mut context) = setup();
+
+        testing_env!(context.predecessor_account_id(user1()).build());
+        contract.fees_set_config(None);
+    }
+
+    #[should_panic(expected = "ERR_TREASURY_CANNOT_UNREGISTER")]
+    #[test]
+    fn test_treasury_cannot_unregister_while_fee_is_set() {
+        let (mut contract, mut context) = setup_fee(0, None);
+
+        testing_env!(context
+            .predecessor_account_id(treasury())
+            .attached_deposit(ONE_YOCTO)
+            .build());
+        contract.storage_unregister(Some(true));
+    }
+
+    #[test]
+    fn test_treasury_can_unregister_once_fee_is_disabled() {
+        let (mut contract, mut context) = setup_fee(0, None);
+
+        testing_env!(context.predecessor_account_id(owner()).build());
+        contract.fees_set_config(None);
+        testing_env!(context
+            .predecessor_account_id(treasury())
+            .attached_deposit(ONE_YOCTO)
+            .build());
+        assert!(contract.storage_unregister(None));
+    }
+
+    #[should_panic(expected = "ERR_ACCOUNT_FROZEN")]
+    #[test]
+    fn test_transfer_panics_when_treasury_frozen() {
+        let (mut contract, mut context) = setup_fee(0, None);
+        freeze(&mut contract, &mut context, treasury());
+
+        transfer(&mut contract, &mut context, owner(), user1(), 1_000);
+    }
+
+    #[test]
+    fn test_fee_counts_in_stats() {
+        let (mut contract, mut context) = setup_fee(0, None);
+
+        transfer(&mut contract, &mut context, owner(), user1(), 1_000);
+        let stats = contract.ft_account_stats(treasury()).unwrap();
+        assert_eq!((stats.receives, stats.received.0), (1, 10));
+        let stats = contract.ft_account_stats(owner()).unwrap();
+        assert_eq!((stats.sends, stats.sent.0), (2, 1_000));
+    }
+}
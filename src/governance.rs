@@ -0,0 +1,595 @@
+//! Token-weighted governance over the parameters of the contract.
+//!
+//! A proposal carries a single function call on the token contract itself. Holders vote with the
+//! voting power delegated to them at the block before the proposal was created, and once the
+//! voting period is over a successful proposal can be executed by anyone after the execution
+//! delay and until its execution window closes. Only the methods listed in
+//! [`GOVERNABLE_METHODS`] can be proposed. A proposal whose call fails is marked failed and cannot
+//! be executed again.
+use near_contract_standards::fungible_token::Balance;
+use near_sdk::collections::{LookupSet, Vector};
+use near_sdk::json_types::{Base64VecU8, U128, U64};
+use near_sdk::{
+    env, log, near, require, AccountId, BlockHeight, Gas, NearToken, Promise, PromiseError,
+    Timestamp,
+};
+
+use crate::internal::{assert_at_least_one_yocto, refund_deposit};
+use crate::{Contract, ContractExt, StorageKey};
+
+/// Methods of the contract that an executed proposal is allowed to call.
+pub const GOVERNABLE_METHODS: &[&str] = &[
+    "set_session_vault_id",
+    "gov_set_config",
+    "fees_set_config",
+    "fees_add_exempt_accounts",
+    "fees_remove_exempt_accounts",
+    "fees_set_route_override",
+    "compliance_add_officer",
+    "compliance_remove_officer",
+    "set_transfer_mode",
+    "enable_public_trading",
+    "transfer_allowlist_add",
+    "transfer_allowlist_remove",
+    "launch_limits_set",
+    "launch_limits_add_exempt_accounts",
+    "launch_limits_remove_exempt_accounts",
+    "metadata_add_manager",
+    "metadata_remove_manager",
+    "supply_add_non_circulating_accounts",
+    "supply_remove_non_circulating_accounts",
+];
+
+const MAX_BASIS_POINTS: u16 = 10_000;
+const DEFAULT_VOTING_PERIOD: Timestamp = 3 * 24 * 60 * 60 * 1_000_000_000;
+const DEFAULT_EXECUTION_DELAY: Timestamp = 2 * 24 * 60 * 60 * 1_000_000_000;
+const DEFAULT_EXECUTION_WINDOW: Timestamp = 7 * 24 * 60 * 60 * 1_000_000_000;
+const DEFAULT_QUORUM_BPS: u16 = 400;
+/// Default proposal threshold, as a share of the initial supply in basis points.
+const DEFAULT_PROPOSAL_THRESHOLD_BPS: u16 = 10;
+const GAS_FOR_RESOLVE_EXECUTE: Gas = Gas::from_tgas(5);
+
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct GovernanceConfig {
+    /// How long a proposal accepts votes, in nanoseconds.
+    pub voting_period: U64,
+    /// How long a successful proposal waits before it can be executed, in nanoseconds.
+    pub execution_delay: U64,
+    /// How long a successful proposal can be executed once the delay is over, in nanoseconds.
+    pub execution_window: U64,
+    /// Share of the total supply, in basis points, that must vote for or abstain.
+    pub quorum_bps: u16,
+    /// Voting power a proposer needs to create a proposal.
+    pub proposal_threshold: U128,
+}
+
+impl GovernanceConfig {
+    /// Default configuration for a token with `total_supply`.
+    pub fn new(total_supply: Balance) -> Self {
+        Self {
+            voting_period: DEFAULT_VOTING_PERIOD.into(),
+            execution_delay: DEFAULT_EXECUTION_DELAY.into(),
+            execution_window: DEFAULT_EXECUTION_WINDOW.into(),
+            quorum_bps: DEFAULT_QUORUM_BPS,
+            proposal_threshold: (total_supply / Balance::from(MAX_BASIS_POINTS)
+                * Balance::from(DEFAULT_PROPOSAL_THRESHOLD_BPS))
+            .into(),
+        }
+    }
+}
+
+/// Function call the contract makes on itself when a proposal is executed.
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProposalAction {
+    pub method_name: String,
+    pub args: Base64VecU8,
+    pub gas: Gas,
+}
+
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Vote {
+    For,
+    Against,
+    Abstain,
+}
+
+#[near(serializers = [json])]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ProposalState {
+    Active,
+    Defeated,
+    /// Passed, waiting for the execution delay to elapse.
+    Queued,
+    Executable,
+    /// Passed, but not executed before the end of its execution window.
+    Expired,
+    Executed,
+    /// Executed, but its call failed.
+    Failed,
+}
+
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Proposal {
+    pub id: u64,
+    pub proposer: AccountId,
+    pub description: String,
+    pub action: ProposalAction,
+    /// Block whose checkpointed votes are counted.
+    pub snapshot_block: BlockHeight,
+    pub voting_end: U64,
+    pub executable_at: U64,
+    pub expires_at: U64,
+    pub quorum: U128,
+    pub for_votes: U128,
+    pub against_votes: U128,
+    pub abstain_votes: U128,
+    pub executed: bool,
+    pub failed: bool,
+}
+
+impl Proposal {
+    fn state(&self) -> ProposalState {
+        let now = env::block_timestamp();
+        if self.failed {
+            ProposalState::Failed
+        } else if self.executed {
+            ProposalState::Executed
+        } else if now < self.voting_end.0 {
+            ProposalState::Active
+        } else if self.for_votes.0 <= self.against_votes.0
+            || self.for_votes.0.saturating_add(self.abstain_votes.0) < self.quorum.0
+        {
+            ProposalState::Defeated
+        } else if now < self.executable_at.0 {
+            ProposalState::Queued
+        } else if now < self.expires_at.0 {
+            ProposalState::Executable
+        } else {
+            ProposalState::Expired
+        }
+    }
+}
+
+#[near]
+pub struct Governance {
+    config: GovernanceConfig,
+    proposals: Vector<Proposal>,
+    /// (Proposal id, voter) pairs that already voted.
+    receipts: LookupSet<(u64, AccountId)>,
+}
+
+impl Governance {
+    pub fn new(total_supply: Balance) -> Self {
+        Self {
+            config: GovernanceConfig::new(total_supply),
+            proposals: Vector::new(StorageKey::Proposals),
+            receipts: LookupSet::new(StorageKey::ProposalReceipts),
+        }
+    }
+
+    fn proposal(&self, proposal_id: u64) -> Proposal {
+        self.proposals
+            .get(proposal_id)
+            .unwrap_or_else(|| env::panic_str("ERR_PROPOSAL_NOT_FOUND"))
+    }
+}
+
+fn assert_valid_config(config: &GovernanceConfig) {
+    require!(config.quorum_bps <= MAX_BASIS_POINTS, "ERR_INVALID_QUORUM");
+    require!(config.voting_period.0 > 0, "ERR_INVALID_VOTING_PERIOD");
+    require!(
+        config.execution_window.0 > 0,
+        "ERR_INVALID_EXECUTION_WINDOW"
+    );
+    require!(
+        config.proposal_threshold.0 > 0,
+        "ERR_INVALID_PROPOSAL_THRESHOLD"
+    );
+}
+
+#[near]
+impl Contract {
+    /// Creates a proposal to call `action` on this contract. The proposer needs at least
+    /// `proposal_threshold` votes at the previous block and pays for the proposal storage.
+    #[payable]
+    pub fn gov_propose(&mut self, description: String, action: ProposalAction) -> u64 {
+        assert_at_least_one_yocto();
+        let initial_storage_usage = env::storage_usage();
+        require!(
+            GOVERNABLE_METHODS.contains(&action.method_name.as_str()),
+            "ERR_METHOD_NOT_GOVERNABLE"
+        );
+
+        let proposer = env::predecessor_account_id();
+        let snapshot_block = env::block_height() - 1;
+        let config = &self.governance.config;
+        require!(
+            self.votes.past_votes_of(&proposer, snapshot_block) >= config.proposal_threshold.0,
+            "ERR_BELOW_PROPOSAL_THRESHOLD"
+        );
+
+        let voting_end = env::block_timestamp() + config.voting_period.0;
+        let executable_at = voting_end + config.execution_delay.0;
+        let quorum = self.token.total_supply / Balance::from(MAX_BASIS_POINTS)
+            * Balance::from(config.quorum_bps);
+        let id = self.governance.proposals.len();
+        self.votes.record_snapshot(snapshot_block);
+        self.governance.proposals.push(&Proposal {
+            id,
+            proposer,
+            description,
+            action,
+            snapshot_block,
+            voting_end: voting_end.into(),
+            executable_at: executable_at.into(),
+            expires_at: (executable_at + config.execution_window.0).into(),
+            quorum: quorum.into(),
+            for_votes: 0.into(),
+            against_votes: 0.into(),
+            abstain_votes: 0.into(),
+            executed: false,
+            failed: false,
+        });
+
+        refund_deposit(initial_storage_usage);
+        id
+    }
+
+    /// Casts the predecessor's votes at the proposal snapshot. Each account votes once.
+    #[payable]
+    pub fn gov_vote(&mut self, proposal_id: u64, vote: Vote) {
+        assert_at_least_one_yocto();
+        let initial_storage_usage = env::storage_usage();
+        let voter = env::predecessor_account_id();
+        let mut proposal = self.governance.proposal(proposal_id);
+        require!(
+            proposal.state() == ProposalState::Active,
+            "ERR_PROPOSAL_NOT_ACTIVE"
+        );
+        require!(
+            self.governance
+                .receipts
+                .insert(&(proposal_id, voter.clone())),
+            "ERR_ALREADY_VOTED"
+        );
+
+        let weight = self.votes.past_votes_of(&voter, proposal.snapshot_block);
+        require!(weight > 0, "ERR_NO_VOTING_POWER");
+        let tally = match vote {
+            Vote::For => &mut proposal.for_votes,
+            Vote::Against => &mut proposal.against_votes,
+            Vote::Abstain => &mut proposal.abstain_votes,
+        };
+        tally.0 += weight;
+        self.governance.proposals.replace(proposal_id, &proposal);
+
+        refund_deposit(initial_storage_usage);
+    }
+
+    /// Executes a successful proposal once its execution delay has elapsed and before its
+    /// execution window closes. The proposal is marked failed if its call fails.
+    pub fn gov_execute(&mut self, proposal_id: u64) -> Promise {
+        let mut proposal = self.governance.proposal(proposal_id);
+        require!(
+            proposal.state() == ProposalState::Executable,
+            "ERR_PROPOSAL_NOT_EXECUTABLE"
+        );
+        proposal.executed = true;
+        self.governance.proposals.replace(proposal_id, &proposal);
+
+        Promise::new(env::current_account_id())
+            .function_call(
+                proposal.action.method_name,
+                proposal.action.args.0,
+                NearToken::from_yoctonear(0),
+                proposal.action.gas,
+            )
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_RESOLVE_EXECUTE)
+                    .gov_resolve_execute(proposal_id),
+            )
+    }
+
+    /// Returns whether the call of the proposal succeeded, marking the proposal failed if not.
+    #[private]
+    pub fn gov_resolve_execute(
+        &mut self,
+        proposal_id: u64,
+        #[callback_result] result: Result<(), PromiseError>,
+    ) -> bool {
+        if result.is_ok() {
+            return true;
+        }
+        let mut proposal = self.governance.proposal(proposal_id);
+        proposal.failed = true;
+        self.governance.proposals.replace(proposal_id, &proposal);
+        log!("Proposal {} failed to execute", proposal_id);
+        false
+    }
+
+    pub fn gov_set_config(&mut self, config: GovernanceConfig) {
+        self.assert_owner();
+        assert_valid_config(&config);
+        self.governance.config = config;
+    }
+
+    pub fn gov_config(&self) -> GovernanceConfig {
+        self.governance.config.clone()
+    }
+
+    pub fn gov_proposal(&self, proposal_id: u64) -> Option<Proposal> {
+        self.governance.proposals.get(proposal_id)
+    }
+
+    pub fn gov_proposal_state(&self, proposal_id: u64) -> ProposalState {
+        self.governance.proposal(proposal_id).state()
+    }
+
+    pub fn gov_proposals(&self, from_index: Option<u64>, limit: Option<u64>) -> Vec<Proposal> {
+        self.governance
+            .proposals
+            .iter()
+            .skip(from_index.unwrap_or(0) as usize)
+            .take(limit.unwrap_or(u64::MAX) as usize)
+            .collect()
+    }
+
+    pub fn gov_has_voted(&self, proposal_id: u64, account_id: AccountId) -> bool {
+        self.governance
+            .receipts
+            .contains(&(proposal_id, account_id))
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::VMContextBuilder;
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_utils::*;
+
+    fn session_vault_action(session_vault_id: &AccountId) -> ProposalAction {
+        ProposalAction {
+            method_name: "set_session_vault_id".to_string(),
+            args: near_sdk::serde_json::to_vec(
+                &near_sdk::serde_json::json!({ "session_vault_id": session_vault_id }),
+            )
+            .unwrap()
+            .into(),
+            gas: Gas::from_tgas(10),
+        }
+    }
+
+    /// Registers user1 with a quarter of the supply and delegates both holders to themselves.
+    fn setup_voters() -> (Contract, VMContextBuilder) {
+        let (mut contract, mut context) = setup();
+        register(&mut contract, &mut context, user1());
+        transfer(
+            &mut contract,
+            &mut context,
+            owner(),
+            user1(),
+            TOTAL_SUPPLY / 4,
+        );
+
+        for account_id in [owner(), user1()] {
+            testing_env!(context
+                .block_height(1)
+                .predecessor_account_id(account_id.clone())
+                .attached_deposit(ONE_NEAR)
+                .build());
+            contract.delegate(account_id);
+        }
+
+        testing_env!(context.block_height(2).block_timestamp(0).build());
+        (contract, context)
+    }
+
+    fn propose(contract: &mut Contract, context: &mut VMContextBuilder) -> u64 {
+        testing_env!(context
+            .predecessor_account_id(user1())
+            .attached_deposit(ONE_NEAR)
+            .build());
+        contract.gov_propose(
+            "Set session vault".to_string(),
+            session_vault_action(&user2()),
+        )
+    }
+
+    fn vote(contract: &mut Contract, context: &mut VMContextBuilder, voter: AccountId, vote: Vote) {
+        testing_env!(context
+            .predecessor_account_id(voter)
+            .attached_deposit(ONE_NEAR)
+            .build());
+        contract.gov_vote(0, vote);
+    }
+
+    #[test]
+    fn test_proposal_lifecycle() {
+        let (mut contract, mut context) = setup_voters();
+        let proposal_id = propose(&mut contract, &mut context);
+        assert_eq!(
+            contract.gov_proposal_state(proposal_id),
+            ProposalState::Active
+        );
+
+        vote(&mut contract, &mut context, owner(), Vote::For);
+        vote(&mut contract, &mut context, user1(), Vote::Against);
+        let proposal = contract.gov_proposal(proposal_id).unwrap();
+        assert_eq!(proposal.for_votes.0, TOTAL_SUPPLY / 4 * 3);
+        assert_eq!(proposal.against_votes.0, TOTAL_SUPPLY / 4);
+
+        testing_env!(context.block_timestamp(DEFAULT_VOTING_PERIOD).build());
+        assert_eq!(
+            contract.gov_proposal_state(proposal_id),
+            ProposalState::Queued
+        );
+
+        testing_env!(context
+            .block_timestamp(DEFAULT_VOTING_PERIOD + DEFAULT_EXECUTION_DELAY)
+            .build());
+        assert_eq!(
+            contract.gov_proposal_state(proposal_id),
+            ProposalState::Executable
+        );
+        contract.gov_execute(proposal_id);
+        assert_eq!(
+            contract.gov_proposal_state(proposal_id),
+            ProposalState::Executed
+        );
+    }
+
+    #[test]
+    fn test_proposal_defeated() {
+        let (mut contract, mut context) = setup_voters();
+        let proposal_id = propose(&mut contract, &mut context);
+
+        vote(&mut contract, &mut context, user1(), Vote::For);
+        vote(&mut contract, &mut context, owner(), Vote::Against);
+
+        testing_env!(context.block_timestamp(DEFAULT_VOTING_PERIOD).build());
+        assert_eq!(
+            contract.gov_proposal_state(proposal_id),
+            ProposalState::Defeated
+        );
+    }
+
+    #[test]
+    fn test_votes_use_snapshot() {
+        let (mut contract, mut context) = setup_voters();
+        let proposal_id = propose(&mut contract, &mut context);
+
+        // tokens moved after the snapshot don't change the weight of the vote
+        testing_env!(context.block_height(3).build());
+        transfer(
+            &mut contract,
+            &mut context,
+            owner(),
+            user1(),
+            TOTAL_SUPPLY / 4,
+        );
+        vote(&mut contract, &mut context, user1(), Vote::For);
+
+        let proposal = contract.gov_proposal(proposal_id).unwrap();
+        assert_eq!(proposal.for_votes.0, TOTAL_SUPPLY / 4);
+    }
+
+    #[should_panic(expected = "ERR_ALREADY_VOTED")]
+    #[test]
+    fn test_vote_panics_on_second_vote() {
+        let (mut contract, mut context) = setup_voters();
+        propose(&mut contract, &mut context);
+
+        vote(&mut contract, &mut context, user1(), Vote::For);
+        vote(&mut contract, &mut context, user1(), Vote::Against);
+    }
+
+    #[should_panic(expected = "ERR_PROPOSAL_NOT_EXECUTABLE")]
+    #[test]
+    fn test_failed_execution_cannot_be_retried() {
+        let (mut contract, mut context) = setup_voters();
+        let proposal_id = propose(&mut contract, &mut context);
+        vote(&mut contract, &mut context, owner(), Vote::For);
+
+        testing_env!(context
+            .block_timestamp(DEFAULT_VOTING_PERIOD + DEFAULT_EXECUTION_DELAY)
+            .build());
+        contract.gov_execute(proposal_id);
+
+        testing_env!(context.predecessor_account_id(current()).build());
+        assert!(!contract.gov_resolve_execute(proposal_id, Err(PromiseError::Failed)));
+        assert_eq!(
+            contract.gov_proposal_state(proposal_id),
+            ProposalState::Failed
+        );
+        contract.gov_execute(proposal_id);
+    }
+
+    #[should_panic(expected = "ERR_PROPOSAL_NOT_EXECUTABLE")]
+    #[test]
+    fn test_execute_panics_after_window() {
+        let (mut contract, mut context) = setup_voters();
+        let proposal_id = propose(&mut contract, &mut context);
+        vote(&mut contract, &mut context, owner(), Vote::For);
+
+        testing_env!(context
+            .block_timestamp(
+                DEFAULT_VOTING_PERIOD + DEFAULT_EXECUTION_DELAY + DEFAULT_EXECUTION_WINDOW
+            )
+            .build());
+        assert_eq!(
+            contract.gov_proposal_state(proposal_id),
+            ProposalState::Expired
+        );
+        contract.gov_execute(proposal_id);
+    }
+
+    #[should_panic(expected = "ERR_BELOW_PROPOSAL_THRESHOLD")]
+    #[test]
+    fn test_propose_panics_below_threshold() {
+        let (mut contract, mut context) = setup_voters();
+        assert_eq!(
+            contract.gov_config().proposal_threshold.0,
+            TOTAL_SUPPLY / 1_000
+        );
+
+        testing_env!(context
+            .predecessor_account_id(user2())
+            .attached_deposit(ONE_NEAR)
+            .build());
+        contract.gov_propose(
+            "Set session vault".to_string(),
+            session_vault_action(&user2()),
+        );
+    }
+
+    #[should_panic(expected = "ERR_PROPOSAL_NOT_EXECUTABLE")]
+    #[test]
+    fn test_execute_panics_before_delay() {
+        let (mut contract, mut context) = setup_voters();
+        let proposal_id = propose(&mut contract, &mut context);
+        vote(&mut contract, &mut context, owner(), Vote::For);
+
+        testing_env!(context.block_timestamp(DEFAULT_VOTING_PERIOD).build());
+        contract.gov_execute(proposal_id);
+    }
+
+    #[should_panic(expected = "ERR_METHOD_NOT_GOVERNABLE")]
+    #[test]
+    fn test_propose_panics_on_non_governable_method() {
+        let (mut contract, mut context) = setup_voters();
+
+        testing_env!(context
+            .predecessor_account_id(user1())
+            .attached_deposit(ONE_NEAR)
+            .build());
+        contract.gov_propose(
+            "Drain".to_string(),
+            ProposalAction {
+                method_name: "ft_transfer".to_string(),
+                args: vec![].into(),
+                gas: Gas::from_tgas(10),
+            },
+        );
+    }
+
+    #[test]
+    fn test_executed_proposal_can_call_owner_methods() {
+        let (mut contract, mut context) = setup_voters();
+
+        // the contract calling itself is how an executed proposal reaches owner-only methods
+        testing_env!(context.predecessor_account_id(current()).build());
+        contract.set_session_vault_id(user2());
+        contract.gov_set_config(GovernanceConfig {
+            quorum_bps: 1_000,
+            ..contract.gov_config()
+        });
+        assert_eq!(contract.gov_config().quorum_bps, 1_000);
+    }
+}
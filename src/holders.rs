@@ -0,0 +1,102 @@
+//! Iterable index of the registered accounts.
+//!
+//! Balances live in the `LookupMap` of `FungibleToken`, which cannot be enumerated, so every
+//! registered account is also kept in an `UnorderedSet` from registration to unregistration.
+//! Its storage is part of the registration cost returned by `storage_balance_bounds`.
+use near_sdk::collections::UnorderedSet;
+use near_sdk::{env, near, AccountId, StorageUsage};
+
+use crate::{Contract, ContractExt, StorageKey};
+
+#[near]
+pub struct Holders {
+    accounts: UnorderedSet<AccountId>,
+}
+
+impl Holders {
+    pub fn new() -> Self {
+        Self {
+            accounts: UnorderedSet::new(StorageKey::Holders),
+        }
+    }
+
+    /// Storage used by the index entry of an account with the longest possible id.
+    pub fn measure_account_storage_usage(&mut self) -> StorageUsage {
+        let initial_storage_usage = env::storage_usage();
+        let tmp_account_id: AccountId = "a".repeat(64).parse().unwrap();
+        self.accounts.insert(&tmp_account_id);
+        let account_storage_usage = env::storage_usage() - initial_storage_usage;
+        self.accounts.remove(&tmp_account_id);
+        account_storage_usage
+    }
+
+    pub fn insert(&mut self, account_id: &AccountId) {
+        self.accounts.insert(account_id);
+    }
+
+    pub fn remove(&mut self, account_id: &AccountId) {
+        self.accounts.remove(account_id);
+    }
+}
+
+#[near]
+impl Contract {
+    /// Returns the registered accounts, in no particular order. The order only changes when an
+    /// account unregisters, which moves the last account into its place.
+    pub fn ft_holders(&self, from_index: Option<u64>, limit: Option<u64>) -> Vec<AccountId> {
+        self.holders
+            .accounts
+            .iter()
+            .skip(from_index.unwrap_or(0) as usize)
+            .take(limit.unwrap_or(u64::MAX) as usize)
+            .collect()
+    }
+
+    pub fn ft_holders_count(&self) -> u64 {
+        self.holders.accounts.len()
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_contract_standards::storage_management::StorageManagement;
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_utils::*;
+
+    #[test]
+    fn test_holders_follow_registrations() {
+        let (mut contract, mut context) = setup();
+        assert_eq!(contract.ft_holders(None, None), vec![owner()]);
+
+        register(&mut contract, &mut context, user1());
+        register(&mut contract, &mut context, user2());
+        assert_eq!(contract.ft_holders_count(), 3);
+        assert_eq!(contract.ft_holders(Some(1), Some(1)), vec![user1()]);
+
+        testing_env!(context
+            .predecessor_account_id(user1())
+            .attached_deposit(ONE_YOCTO)
+            .build());
+        contract.storage_unregister(None);
+        assert_eq!(contract.ft_holders(None, None), vec![owner(), user2()]);
+    }
+
+    #[test]
+    fn test_registration_pays_for_storage() {
+        let (mut contract, mut context) = setup();
+        let account_id: AccountId = "b".repeat(64).parse().unwrap();
+
+        testing_env!(context
+            .predecessor_account_id(account_id)
+            .attached_deposit(contract.storage_balance_bounds().min)
+            .build());
+        let initial_storage_usage = env::storage_usage();
+        contract.storage_deposit(None, None);
+
+        assert!(
+            env::storage_usage() - initial_storage_usage <= contract.token.account_storage_usage
+        );
+    }
+}
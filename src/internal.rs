@@ -0,0 +1,59 @@
+use std::convert::TryInto;
+
+use near_sdk::json_types::Base64VecU8;
+use near_sdk::{env, require, AccountId, NearToken, Promise, PublicKey, StorageUsage};
+
+/// Charges the predecessor for the storage added since `initial_storage_usage` out of the
+/// attached deposit and refunds whatever is left over.
+pub(crate) fn refund_deposit(initial_storage_usage: StorageUsage) {
+    let storage_used = env::storage_usage().saturating_sub(initial_storage_usage);
+    let required_cost = env::storage_byte_cost().saturating_mul(storage_used.into());
+    let attached_deposit = env::attached_deposit();
+
+    require!(
+        attached_deposit >= required_cost,
+        format!(
+            "ERR_INSUFFICIENT_STORAGE_DEPOSIT: {} yoctoNEAR is required",
+            required_cost.as_yoctonear()
+        )
+    );
+
+    let refund = attached_deposit.saturating_sub(required_cost);
+    if refund > NearToken::from_yoctonear(0) {
+        let _ = Promise::new(env::predecessor_account_id()).transfer(refund);
+    }
+}
+
+/// Requires a non-zero deposit, so the call can only be signed with a full access key.
+pub(crate) fn assert_at_least_one_yocto() {
+    require!(
+        env::attached_deposit() >= NearToken::from_yoctonear(1),
+        "Requires attached deposit of at least 1 yoctoNEAR"
+    );
+}
+
+/// Refunds `account_id` for the storage released since `initial_storage_usage`.
+pub(crate) fn refund_released_storage(account_id: &AccountId, initial_storage_usage: StorageUsage) {
+    let storage_released = initial_storage_usage.saturating_sub(env::storage_usage());
+    if storage_released > 0 {
+        let _ = Promise::new(account_id.clone())
+            .transfer(env::storage_byte_cost().saturating_mul(storage_released.into()));
+    }
+}
+
+/// Checks an ed25519 `signature` of `message` by `public_key`.
+pub(crate) fn verify_ed25519(
+    public_key: &PublicKey,
+    message: &[u8],
+    signature: &Base64VecU8,
+) -> bool {
+    let signature: [u8; 64] = signature
+        .0
+        .as_slice()
+        .try_into()
+        .unwrap_or_else(|_| env::panic_str("ERR_INVALID_SIGNATURE_LENGTH"));
+    let public_key: [u8; 32] = public_key.as_bytes()[1..]
+        .try_into()
+        .unwrap_or_else(|_| env::panic_str("ERR_INVALID_PUBLIC_KEY_LENGTH"));
+    env::ed25519_verify(&signature, message, &public_key)
+}
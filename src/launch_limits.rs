@@ -0,0 +1,209 @@
+//! Anti-whale limits for the days following the listing.
+//!
+//! Until `expires_at`, accounts that are not exempt cannot send more than `max_transfer` in a
+//! single transfer, nor receive tokens past a balance of `max_balance`. The limits apply to
+//! `ft_transfer`, `ft_transfer_call` and to the tokens locked into the features of the contract,
+//! which share the same checks. The payouts of those features, refunds included, are checked
+//! against the `max_balance` of their recipient; refunds of `ft_transfer_call` are not limited.
+//! The contract account is always exempt.
+use near_contract_standards::fungible_token::Balance;
+use near_sdk::collections::UnorderedSet;
+use near_sdk::json_types::{U128, U64};
+use near_sdk::{env, near, require, AccountId};
+
+use crate::{Contract, ContractExt, StorageKey};
+
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct LaunchLimitsConfig {
+    pub max_transfer: U128,
+    pub max_balance: U128,
+    /// Block timestamp in nanoseconds from which the limits no longer apply.
+    pub expires_at: U64,
+}
+
+#[near]
+pub struct LaunchLimits {
+    config: Option<LaunchLimitsConfig>,
+    exempt: UnorderedSet<AccountId>,
+}
+
+impl LaunchLimits {
+    pub fn new() -> Self {
+        Self {
+            config: None,
+            exempt: UnorderedSet::new(StorageKey::LaunchLimitsExempt),
+        }
+    }
+
+    fn active_config(&self) -> Option<&LaunchLimitsConfig> {
+        self.config
+            .as_ref()
+            .filter(|config| env::block_timestamp() < config.expires_at.0)
+    }
+
+    fn is_exempt(&self, account_id: &AccountId) -> bool {
+        *account_id == env::current_account_id() || self.exempt.contains(account_id)
+    }
+}
+
+impl Contract {
+    pub(crate) fn assert_within_launch_limits(
+        &self,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+        amount: Balance,
+    ) {
+        if let Some(config) = self.launch_limits.active_config() {
+            if !self.launch_limits.is_exempt(sender_id) {
+                require!(
+                    amount <= config.max_transfer.0,
+                    "ERR_MAX_TRANSFER_AMOUNT_EXCEEDED"
+                );
+            }
+            if !self.launch_limits.is_exempt(receiver_id) {
+                let balance = self.token.accounts.get(receiver_id).unwrap_or(0);
+                require!(
+                    balance.saturating_add(amount) <= config.max_balance.0,
+                    "ERR_RECIPIENT_MAX_BALANCE_EXCEEDED"
+                );
+            }
+        }
+    }
+}
+
+#[near]
+impl Contract {
+    /// Sets the launch limits, or removes them when `config` is `None`.
+    pub fn launch_limits_set(&mut self, config: Option<LaunchLimitsConfig>) {
+        self.assert_owner();
+        if let Some(config) = config.as_ref() {
+            require!(
+                config.max_transfer.0 > 0 && config.max_balance.0 > 0,
+                "ERR_ZERO_LAUNCH_LIMIT"
+            );
+        }
+        self.launch_limits.config = config;
+    }
+
+    pub fn launch_limits_add_exempt_accounts(&mut self, account_ids: Vec<AccountId>) {
+        self.assert_owner();
+        for account_id in account_ids.iter() {
+            self.launch_limits.exempt.insert(account_id);
+        }
+    }
+
+    pub fn launch_limits_remove_exempt_accounts(&mut self, account_ids: Vec<AccountId>) {
+        self.assert_owner();
+        for account_id in account_ids.iter() {
+            self.launch_limits.exempt.remove(account_id);
+        }
+    }
+
+    /// Returns the launch limits, including expired ones.
+    pub fn launch_limits(&self) -> Option<LaunchLimitsConfig> {
+        self.launch_limits.config.clone()
+    }
+
+    pub fn launch_limits_active(&self) -> bool {
+        self.launch_limits.active_config().is_some()
+    }
+
+    pub fn launch_limits_exempt_accounts(
+        &self,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> Vec<AccountId> {
+        self.launch_limits
+            .exempt
+            .iter()
+            .skip(from_index.unwrap_or(0) as usize)
+            .take(limit.unwrap_or(u64::MAX) as usize)
+            .collect()
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_contract_standards::fungible_token::FungibleTokenCore;
+    use near_sdk::test_utils::VMContextBuilder;
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_utils::*;
+
+    const EXPIRES_AT: u64 = 1_000;
+
+    fn setup_limits() -> (Contract, VMContextBuilder) {
+        let (mut contract, mut context) = setup();
+        register(&mut contract, &mut context, user1());
+        register(&mut contract, &mut context, user2());
+
+        testing_env!(context
+            .predecessor_account_id(owner())
+            .block_timestamp(0)
+            .build());
+        contract.launch_limits_add_exempt_accounts(vec![owner()]);
+        contract.launch_limits_set(Some(LaunchLimitsConfig {
+            max_transfer: 100.into(),
+            max_balance: 150.into(),
+            expires_at: EXPIRES_AT.into(),
+        }));
+
+        (contract, context)
+    }
+
+    #[test]
+    fn test_exempt_sender_is_not_limited() {
+        let (mut contract, mut context) = setup_limits();
+
+        transfer(&mut contract, &mut context, owner(), user1(), 150);
+        assert_eq!(contract.ft_balance_of(user1()).0, 150);
+        assert!(contract.launch_limits_active());
+    }
+
+    #[should_panic(expected = "ERR_MAX_TRANSFER_AMOUNT_EXCEEDED")]
+    #[test]
+    fn test_max_transfer() {
+        let (mut contract, mut context) = setup_limits();
+        transfer(&mut contract, &mut context, owner(), user1(), 150);
+
+        transfer(&mut contract, &mut context, user1(), user2(), 101);
+    }
+
+    #[should_panic(expected = "ERR_RECIPIENT_MAX_BALANCE_EXCEEDED")]
+    #[test]
+    fn test_max_balance() {
+        let (mut contract, mut context) = setup_limits();
+        transfer(&mut contract, &mut context, owner(), user1(), 150);
+
+        transfer(&mut contract, &mut context, owner(), user1(), 1);
+    }
+
+    #[should_panic(expected = "ERR_RECIPIENT_MAX_BALANCE_EXCEEDED")]
+    #[test]
+    fn test_max_balance_applies_to_unlocks() {
+        let (mut contract, mut context) = setup_limits();
+
+        testing_env!(context
+            .predecessor_account_id(owner())
+            .attached_deposit(ONE_NEAR)
+            .build());
+        contract.escrow_create(user1(), 200.into(), user2(), 1.into());
+
+        testing_env!(context.attached_deposit(ONE_YOCTO).build());
+        contract.escrow_release(0);
+    }
+
+    #[test]
+    fn test_limits_expire() {
+        let (mut contract, mut context) = setup_limits();
+        transfer(&mut contract, &mut context, owner(), user1(), 150);
+
+        testing_env!(context.block_timestamp(EXPIRES_AT).build());
+        assert!(!contract.launch_limits_active());
+        transfer(&mut contract, &mut context, owner(), user1(), 1_000);
+        transfer(&mut contract, &mut context, user1(), user2(), 1_000);
+        assert_eq!(contract.ft_balance_of(user2()).0, 1_000);
+    }
+}
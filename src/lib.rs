@@ -14,16 +14,14 @@ NOTES:
     attach more deposit than required.
   - To prevent the deployed contract from being modified or deleted, it should not have any access
     keys on its account.
+  - The state layout is not compatible with the original token contract and there is no
+    `migrate` method: deploy to a fresh account and initialize with `new` or `new_default_meta`.
 */
-use base64::{
-    engine::general_purpose::{self, GeneralPurpose},
-    Engine,
-};
 use near_contract_standards::fungible_token::metadata::{
     FungibleTokenMetadata, FungibleTokenMetadataProvider, FT_METADATA_SPEC,
 };
 use near_contract_standards::fungible_token::{
-    FungibleToken, FungibleTokenCore, FungibleTokenResolver,
+    Balance, FungibleToken, FungibleTokenCore, FungibleTokenResolver,
 };
 use near_contract_standards::storage_management::{
     StorageBalance, StorageBalanceBounds, StorageManagement,
@@ -36,7 +34,65 @@ use near_sdk::{
     env, log, near, AccountId, BorshStorageKey, NearToken, PanicOnDefault, PromiseOrValue,
 };
 
-const DATA_IMAGE_SVG_ITLX_ICON: &str = "data:image/svg+xml,%3Csvg version='1.0' xmlns='http://www.w3.org/2000/svg' width='721.000000pt' height='399.000000pt' viewBox='0 0 721.000000 399.000000' preserveAspectRatio='xMidYMid meet'%3E%3Cg transform='translate(0.000000,399.000000) scale(0.100000,-0.100000)' fill='%23000000' stroke='none'%3E%3Cpath d='M0 1995 l0 -1995 3605 0 3605 0 0 1995 0 1995 -3605 0 -3605 0 0 -1995z m2888 1200 c110 -22 190 -64 252 -132 183 -200 178 -507 -15 -830 -75 -126 -101 -152 -50 -49 163 327 192 597 83 769 -58 91 -160 160 -277 187 -81 19 -231 15 -351 -10 -134 -27 -260 -74 -438 -161 l-143 -71 46 -50 c57 -63 109 -151 137 -231 32 -89 32 -263 1 -362 -70 -221 -249 -381 -473 -421 -129 -23 -268 -7 -325 38 -34 27 -65 92 -65 138 0 83 188 426 362 660 l33 45 -64 -50 c-342 -266 -660 -644 -817 -970 -168 -350 -171 -585 -9 -734 65 -59 135 -87 243 -100 307 -34 733 104 1261 408 60 34 45 14 -42 -57 -438 -358 -1180 -536 -1521 -365 -69 34 -140 111 -167 181 -34 85 -32 269 4 405 66 249 202 520 394 786 9 12 8 31 -3 81 -18 85 -17 229 1 309 38 159 150 298 298 370 178 87 378 93 570 16 l68 -28 97 46 c345 161 680 228 910 182z'/%3E%3C/g%3E%3C/svg%3E";
+use crate::airdrop::Airdrops;
+use crate::channels::Channels;
+use crate::compliance::Compliance;
+use crate::escrow::Escrows;
+use crate::fees::Fees;
+use crate::governance::Governance;
+use crate::holders::Holders;
+use crate::launch_limits::LaunchLimits;
+use crate::locks::Locks;
+use crate::metadata::MetadataAdmin;
+use crate::outflow_limits::OutflowLimits;
+use crate::sessions::Sessions;
+use crate::stats::Stats;
+use crate::streams::Streams;
+use crate::supply::Supply;
+use crate::top_holders::TopHolders;
+use crate::trading::Trading;
+use crate::votes::Votes;
+
+mod account_index;
+mod airdrop;
+mod batch;
+mod channels;
+mod compliance;
+mod escrow;
+mod events;
+mod fees;
+mod governance;
+mod holders;
+mod internal;
+mod launch_limits;
+mod locks;
+mod metadata;
+mod outflow_limits;
+mod sessions;
+mod stats;
+mod streams;
+mod supply;
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod test_utils;
+mod top_holders;
+mod trading;
+mod votes;
+
+include!(concat!(env!("OUT_DIR"), "/default_metadata.rs"));
+
+/// Metadata used by `new_default_meta`, generated at build time from `metadata.json` and
+/// `svgLogo.svg`.
+pub fn default_metadata() -> FungibleTokenMetadata {
+    FungibleTokenMetadata {
+        spec: FT_METADATA_SPEC.to_string(),
+        name: DEFAULT_NAME.to_string(),
+        symbol: DEFAULT_SYMBOL.to_string(),
+        icon: Some(DEFAULT_ICON.to_string()),
+        reference: Some(DEFAULT_REFERENCE.to_string()),
+        reference_hash: Some(Base64VecU8::from(DEFAULT_REFERENCE_HASH.to_vec())),
+        decimals: DEFAULT_DECIMALS,
+    }
+}
 
 #[derive(PanicOnDefault)]
 #[near(contract_state)]
@@ -45,6 +101,24 @@ pub struct Contract {
     metadata: LazyOption<FungibleTokenMetadata>,
     session_vault_id: Option<AccountId>,
     owner: AccountId,
+    votes: Votes,
+    governance: Governance,
+    locks: Locks,
+    airdrops: Airdrops,
+    streams: Streams,
+    escrows: Escrows,
+    channels: Channels,
+    sessions: Sessions,
+    fees: Fees,
+    compliance: Compliance,
+    trading: Trading,
+    launch_limits: LaunchLimits,
+    outflow_limits: OutflowLimits,
+    metadata_admin: MetadataAdmin,
+    holders: Holders,
+    top_holders: TopHolders,
+    stats: Stats,
+    supply: Supply,
 }
 
 #[derive(BorshSerialize, BorshStorageKey)]
@@ -52,6 +126,41 @@ pub struct Contract {
 enum StorageKey {
     FungibleToken,
     Metadata,
+    Delegates,
+    Checkpoints,
+    NumCheckpoints,
+    DelegationNonces,
+    DelegationKeys,
+    Proposals,
+    ProposalReceipts,
+    LockBuckets,
+    AirdropCampaigns,
+    AirdropClaimed,
+    Streams,
+    StreamsByAccount,
+    Escrows,
+    EscrowsByAccount,
+    Channels,
+    ChannelsByAccount,
+    Sessions,
+    SessionIds,
+    SessionsByAccount,
+    FeeExempt,
+    FeeOverrides,
+    ComplianceOfficers,
+    FrozenAccounts,
+    Seizures,
+    TransferAllowlist,
+    LaunchLimitsExempt,
+    OutflowLimits,
+    MetadataManagers,
+    ExtendedMetadata,
+    MetadataHistory,
+    Holders,
+    TopHolders,
+    TopHolderAccounts,
+    AccountStats,
+    NonCirculatingAccounts,
 }
 
 #[near]
@@ -61,23 +170,7 @@ impl Contract {
     #[private]
     #[init]
     pub fn new_default_meta(owner_id: AccountId, total_supply: U128) -> Self {
-        let engine: GeneralPurpose = general_purpose::STANDARD;
-        let decoded: Vec<u8> = engine
-            .decode("K29udivYwweOUnCZPFt/KhcMmm0DQLvzYoVdKXN41P8=")
-            .expect("ERR_FAILED_TO_DECODE_REFERENCE_HASH");
-        Self::new(
-            owner_id,
-            total_supply,
-            FungibleTokenMetadata {
-                spec: FT_METADATA_SPEC.to_string(),
-                name: "Intellex AI Protocol Token".to_string(),
-                symbol: "ITLX".to_string(),
-                icon: Some(DATA_IMAGE_SVG_ITLX_ICON.to_string()),
-                reference: Some("https://raw.githubusercontent.com/brainstems/itlx_nep141_token/refs/heads/master/metadata.json".to_string()),
-                reference_hash: Some(Base64VecU8::from(decoded)),
-                decimals: 24,
-            },
-        )
+        Self::new(owner_id, total_supply, default_metadata())
     }
 
     /// Initializes the contract with the given total supply owned by the given `owner_id` with
@@ -91,9 +184,30 @@ impl Contract {
             metadata: LazyOption::new(StorageKey::Metadata, Some(metadata)),
             session_vault_id: None,
             owner: env::signer_account_id(),
+            votes: Votes::new(),
+            governance: Governance::new(total_supply.0),
+            locks: Locks::new(),
+            airdrops: Airdrops::new(),
+            streams: Streams::new(),
+            escrows: Escrows::new(),
+            channels: Channels::new(),
+            sessions: Sessions::new(),
+            fees: Fees::new(),
+            compliance: Compliance::new(),
+            trading: Trading::new(),
+            launch_limits: LaunchLimits::new(),
+            outflow_limits: OutflowLimits::new(),
+            metadata_admin: MetadataAdmin::new(),
+            holders: Holders::new(),
+            top_holders: TopHolders::new(),
+            stats: Stats::new(),
+            supply: Supply::new(),
         };
-        this.token.internal_register_account(&owner_id);
+        this.token.account_storage_usage += this.holders.measure_account_storage_usage()
+            + this.stats.measure_account_storage_usage();
+        this.internal_register_account(&owner_id);
         this.token.internal_deposit(&owner_id, total_supply.into());
+        this.on_balance_change(None, Some(&owner_id), total_supply.into());
 
         near_contract_standards::fungible_token::events::FtMint {
             owner_id: &owner_id,
@@ -106,11 +220,70 @@ impl Contract {
     }
 
     pub fn set_session_vault_id(&mut self, session_vault_id: AccountId) {
-        require!(env::predecessor_account_id().eq(&self.owner));
+        self.assert_owner();
         self.session_vault_id = Some(session_vault_id);
     }
 }
 
+impl Contract {
+    /// Owner-only methods are also reachable by the contract itself, which is how executed
+    /// governance proposals call them.
+    fn assert_owner(&self) {
+        let predecessor_account_id = env::predecessor_account_id();
+        require!(
+            predecessor_account_id == self.owner
+                || predecessor_account_id == env::current_account_id(),
+            "ERR_NOT_OWNER"
+        );
+    }
+
+    /// Checks the restrictions on moving `amount` tokens from `sender_id` to `receiver_id` and
+    /// records the outflow, for the transfer methods and the features locking tokens.
+    fn internal_check_transfer(
+        &mut self,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+        amount: Balance,
+    ) {
+        self.assert_not_frozen(sender_id);
+        self.assert_not_frozen(receiver_id);
+        self.assert_transfer_mode_allows(sender_id, receiver_id);
+        self.assert_within_launch_limits(sender_id, receiver_id, amount);
+        self.assert_unlocked_balance(sender_id, amount);
+        self.internal_record_outflow(sender_id, amount);
+    }
+
+    /// Called after every change of token balances, `None` standing for the mint or burn side.
+    fn on_balance_change(
+        &mut self,
+        from: Option<&AccountId>,
+        to: Option<&AccountId>,
+        amount: Balance,
+    ) {
+        self.internal_move_delegate_votes(from, to, amount);
+        self.internal_update_top_holders(from);
+        self.internal_update_top_holders(to);
+    }
+
+    /// Registers `account_id` with the token, without charging for its storage.
+    pub(crate) fn internal_register_account(&mut self, account_id: &AccountId) {
+        self.token.internal_register_account(account_id);
+        self.on_account_registered(account_id);
+    }
+
+    /// Called after an account is registered. The storage used here must be counted in
+    /// `token.account_storage_usage`, which is what registration costs.
+    fn on_account_registered(&mut self, account_id: &AccountId) {
+        self.holders.insert(account_id);
+        self.stats.insert(account_id);
+    }
+
+    fn on_account_closed(&mut self, account_id: &AccountId) {
+        self.holders.remove(account_id);
+        self.stats.remove(account_id);
+    }
+}
+
 #[near]
 impl FungibleTokenCore for Contract {
     #[payable]
@@ -121,7 +294,13 @@ impl FungibleTokenCore for Contract {
                 "ERR_RECIPIENT_CANNOT_BE_SESSION_VAULT"
             );
         }
-        self.token.ft_transfer(receiver_id, amount, memo)
+        let sender_id = env::predecessor_account_id();
+        self.internal_check_transfer(&sender_id, &receiver_id, amount.0);
+        let amount = self.internal_charge_transfer_fee(&sender_id, &receiver_id, amount.0);
+        self.token
+            .ft_transfer(receiver_id.clone(), amount.into(), memo);
+        self.on_balance_change(Some(&sender_id), Some(&receiver_id), amount);
+        self.internal_record_transfer_stats(&sender_id, &receiver_id, amount);
     }
 
     #[payable]
@@ -132,7 +311,15 @@ impl FungibleTokenCore for Contract {
         memo: Option<String>,
         msg: String,
     ) -> PromiseOrValue<U128> {
-        self.token.ft_transfer_call(receiver_id, amount, memo, msg)
+        let sender_id = env::predecessor_account_id();
+        self.internal_check_transfer(&sender_id, &receiver_id, amount.0);
+        let amount = self.internal_charge_transfer_fee(&sender_id, &receiver_id, amount.0);
+        let promise = self
+            .token
+            .ft_transfer_call(receiver_id.clone(), amount.into(), memo, msg);
+        self.on_balance_change(Some(&sender_id), Some(&receiver_id), amount);
+        self.internal_record_transfer_stats(&sender_id, &receiver_id, amount);
+        promise
     }
 
     fn ft_total_supply(&self) -> U128 {
@@ -155,9 +342,14 @@ impl FungibleTokenResolver for Contract {
     ) -> U128 {
         let (used_amount, burned_amount) =
             self.token
-                .internal_ft_resolve_transfer(&sender_id, receiver_id, amount);
+                .internal_ft_resolve_transfer(&sender_id, receiver_id.clone(), amount);
         if burned_amount > 0 {
             log!("Account @{} burned {}", sender_id, burned_amount);
+            self.on_balance_change(Some(&receiver_id), None, burned_amount);
+        } else if used_amount < amount.0 {
+            let refund = amount.0 - used_amount;
+            self.on_balance_change(Some(&receiver_id), Some(&sender_id), refund);
+            self.internal_record_transfer_stats(&receiver_id, &sender_id, refund);
         }
         used_amount.into()
     }
@@ -171,7 +363,15 @@ impl StorageManagement for Contract {
         account_id: Option<AccountId>,
         registration_only: Option<bool>,
     ) -> StorageBalance {
-        self.token.storage_deposit(account_id, registration_only)
+        let account_id = account_id.unwrap_or_else(env::predecessor_account_id);
+        let registered = self.token.accounts.contains_key(&account_id);
+        let storage_balance = self
+            .token
+            .storage_deposit(Some(account_id.clone()), registration_only);
+        if !registered {
+            self.on_account_registered(&account_id);
+        }
+        storage_balance
     }
 
     #[payable]
@@ -181,9 +381,22 @@ impl StorageManagement for Contract {
 
     #[payable]
     fn storage_unregister(&mut self, force: Option<bool>) -> bool {
+        require!(
+            env::predecessor_account_id() != env::current_account_id() || self.locks.total() == 0,
+            "ERR_BALANCE_IS_LOCKED"
+        );
+        require!(
+            !self.fees.is_treasury(&env::predecessor_account_id()),
+            "ERR_TREASURY_CANNOT_UNREGISTER"
+        );
+        if force.unwrap_or(false) {
+            self.assert_not_frozen(&env::predecessor_account_id());
+        }
         #[allow(unused_variables)]
         if let Some((account_id, balance)) = self.token.internal_storage_unregister(force) {
             log!("Closed @{} with {}", account_id, balance);
+            self.on_balance_change(Some(&account_id), None, balance);
+            self.on_account_closed(&account_id);
             true
         } else {
             false
@@ -0,0 +1,120 @@
+//! Tokens held by the contract on behalf of its features.
+//!
+//! Locked tokens are moved to the balance of the contract account itself, so they cannot be spent
+//! by their previous owner, and each feature accounts for its share in a [`LockBucket`]. The
+//! contract account may also own regular tokens: only its balance above the total locked amount
+//! can be transferred.
+use near_contract_standards::fungible_token::Balance;
+use near_sdk::collections::UnorderedMap;
+use near_sdk::{env, near, require, AccountId};
+
+use crate::{Contract, StorageKey};
+
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LockBucket {
+    Airdrop,
+    Stream,
+    Escrow,
+    Channel,
+    Session,
+}
+
+#[near]
+pub struct Locks {
+    buckets: UnorderedMap<LockBucket, Balance>,
+    total: Balance,
+}
+
+impl Locks {
+    pub fn new() -> Self {
+        Self {
+            buckets: UnorderedMap::new(StorageKey::LockBuckets),
+            total: 0,
+        }
+    }
+
+    pub fn total(&self) -> Balance {
+        self.total
+    }
+
+    pub fn bucket(&self, bucket: LockBucket) -> Balance {
+        self.buckets.get(&bucket).unwrap_or(0)
+    }
+
+    fn add(&mut self, bucket: LockBucket, amount: Balance) {
+        let locked = self
+            .bucket(bucket)
+            .checked_add(amount)
+            .unwrap_or_else(|| env::panic_str("ERR_LOCKED_BALANCE_OVERFLOW"));
+        self.buckets.insert(&bucket, &locked);
+        self.total += amount;
+    }
+
+    fn sub(&mut self, bucket: LockBucket, amount: Balance) {
+        let locked = self
+            .bucket(bucket)
+            .checked_sub(amount)
+            .unwrap_or_else(|| env::panic_str("ERR_LOCKED_BALANCE_UNDERFLOW"));
+        self.buckets.insert(&bucket, &locked);
+        self.total -= amount;
+    }
+}
+
+impl Contract {
+    /// Moves `amount` tokens of `account_id` into `bucket`.
+    pub(crate) fn internal_lock(
+        &mut self,
+        bucket: LockBucket,
+        account_id: &AccountId,
+        amount: Balance,
+        memo: &str,
+    ) {
+        let contract_id = env::current_account_id();
+        self.internal_check_transfer(account_id, &contract_id, amount);
+        if *account_id != contract_id {
+            if !self.token.accounts.contains_key(&contract_id) {
+                self.internal_register_account(&contract_id);
+            }
+            self.token
+                .internal_transfer(account_id, &contract_id, amount, Some(memo.to_string()));
+            self.on_balance_change(Some(account_id), Some(&contract_id), amount);
+        }
+        self.locks.add(bucket, amount);
+    }
+
+    /// Releases `amount` tokens from `bucket` to `account_id`. `sender_id` is the account that
+    /// locked them, on whose behalf the payout is made.
+    pub(crate) fn internal_unlock(
+        &mut self,
+        bucket: LockBucket,
+        sender_id: &AccountId,
+        account_id: &AccountId,
+        amount: Balance,
+        memo: &str,
+    ) {
+        self.locks.sub(bucket, amount);
+        let contract_id = env::current_account_id();
+        if *account_id != contract_id && amount > 0 {
+            self.assert_not_frozen(sender_id);
+            self.assert_not_frozen(account_id);
+            self.assert_transfer_mode_allows(sender_id, account_id);
+            self.assert_within_launch_limits(&contract_id, account_id, amount);
+            self.token
+                .internal_transfer(&contract_id, account_id, amount, Some(memo.to_string()));
+            self.on_balance_change(Some(&contract_id), Some(account_id), amount);
+            self.internal_record_transfer_stats(sender_id, account_id, amount);
+        }
+    }
+
+    /// Prevents the contract account from spending the tokens it holds for its features.
+    pub(crate) fn assert_unlocked_balance(&self, sender_id: &AccountId, amount: Balance) {
+        if *sender_id == env::current_account_id() {
+            let balance = self.token.accounts.get(sender_id).unwrap_or(0);
+            require!(
+                balance.saturating_sub(self.locks.total()) >= amount,
+                "ERR_BALANCE_IS_LOCKED"
+            );
+        }
+    }
+}
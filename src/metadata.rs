@@ -0,0 +1,389 @@
+//! Updates of the NEP-148 metadata after deployment.
+//!
+//! Metadata managers, appointed by the owner, can fix the icon, the name or the off-chain
+//! reference and its hash without redeploying the contract. Every update is validated with
+//! `assert_valid` and emits an event listing the changed fields. The symbol and the decimals
+//! are part of the identity of the token and can never change.
+//!
+//! Managers also maintain the extended metadata, the details of `metadata.json` that NEP-148 has
+//! no field for, so wallets can show them without fetching the off-chain reference.
+//!
+//! Every update is also appended to the metadata history, which is never pruned.
+use std::collections::BTreeMap;
+
+use near_contract_standards::fungible_token::metadata::FungibleTokenMetadata;
+use near_sdk::collections::{LazyOption, UnorderedSet, Vector};
+use near_sdk::json_types::{Base64VecU8, U64};
+use near_sdk::{env, near, require, AccountId};
+
+use crate::events::Event;
+use crate::{Contract, ContractExt, StorageKey};
+
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ExtendedMetadata {
+    pub description: Option<String>,
+    pub website: Option<String>,
+    /// Social profile URLs keyed by network, e.g. `twitter` or `telegram`.
+    pub socials: BTreeMap<String, String>,
+    pub whitepaper: Option<String>,
+    /// URLs of the published security audits.
+    pub audits: Vec<String>,
+}
+
+impl ExtendedMetadata {
+    /// Names of the fields that differ from `other`.
+    fn changed_fields(&self, other: &ExtendedMetadata) -> Vec<&'static str> {
+        let mut fields = Vec::new();
+        if self.description != other.description {
+            fields.push("description");
+        }
+        if self.website != other.website {
+            fields.push("website");
+        }
+        if self.socials != other.socials {
+            fields.push("socials");
+        }
+        if self.whitepaper != other.whitepaper {
+            fields.push("whitepaper");
+        }
+        if self.audits != other.audits {
+            fields.push("audits");
+        }
+        fields
+    }
+}
+
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct MetadataRevision {
+    pub block_height: U64,
+    pub timestamp: U64,
+    pub updated_by: AccountId,
+    pub fields: Vec<String>,
+    /// Reference hash of the metadata after the update.
+    pub reference_hash: Option<Base64VecU8>,
+}
+
+#[near]
+pub struct MetadataAdmin {
+    managers: UnorderedSet<AccountId>,
+    extended: LazyOption<ExtendedMetadata>,
+    history: Vector<MetadataRevision>,
+}
+
+impl MetadataAdmin {
+    pub fn new() -> Self {
+        Self {
+            managers: UnorderedSet::new(StorageKey::MetadataManagers),
+            extended: LazyOption::new(StorageKey::ExtendedMetadata, None),
+            history: Vector::new(StorageKey::MetadataHistory),
+        }
+    }
+}
+
+/// Names of the fields that differ between `old` and `new`.
+fn changed_fields(old: &FungibleTokenMetadata, new: &FungibleTokenMetadata) -> Vec<&'static str> {
+    let mut fields = Vec::new();
+    if old.spec != new.spec {
+        fields.push("spec");
+    }
+    if old.name != new.name {
+        fields.push("name");
+    }
+    if old.icon != new.icon {
+        fields.push("icon");
+    }
+    if old.reference != new.reference {
+        fields.push("reference");
+    }
+    if old.reference_hash != new.reference_hash {
+        fields.push("reference_hash");
+    }
+    fields
+}
+
+impl Contract {
+    fn assert_metadata_manager(&self) {
+        require!(
+            self.metadata_admin
+                .managers
+                .contains(&env::predecessor_account_id()),
+            "ERR_NOT_METADATA_MANAGER"
+        );
+    }
+
+    fn internal_record_metadata_revision(&mut self, fields: Vec<&'static str>) {
+        let updated_by = env::predecessor_account_id();
+        Event::MetadataUpdated {
+            updated_by: &updated_by,
+            fields: fields.clone(),
+        }
+        .emit();
+        self.metadata_admin.history.push(&MetadataRevision {
+            block_height: env::block_height().into(),
+            timestamp: env::block_timestamp().into(),
+            updated_by,
+            fields: fields.into_iter().map(String::from).collect(),
+            reference_hash: self.metadata.get().as_ref().unwrap().reference_hash.clone(),
+        });
+    }
+
+    fn internal_update_metadata(&mut self, update: impl FnOnce(&mut FungibleTokenMetadata)) {
+        self.assert_metadata_manager();
+        let old = self.metadata.get().clone().unwrap();
+        let mut new = old.clone();
+        update(&mut new);
+
+        require!(new.symbol == old.symbol, "ERR_SYMBOL_IS_IMMUTABLE");
+        require!(new.decimals == old.decimals, "ERR_DECIMALS_ARE_IMMUTABLE");
+        new.assert_valid();
+        let fields = changed_fields(&old, &new);
+        require!(!fields.is_empty(), "ERR_METADATA_UNCHANGED");
+
+        self.metadata.set(Some(new));
+        self.internal_record_metadata_revision(fields);
+    }
+
+    fn internal_update_extended_metadata(&mut self, update: impl FnOnce(&mut ExtendedMetadata)) {
+        self.assert_metadata_manager();
+        let old = self.metadata_admin.extended.get().unwrap_or_default();
+        let mut new = old.clone();
+        update(&mut new);
+
+        let fields = new.changed_fields(&old);
+        require!(!fields.is_empty(), "ERR_METADATA_UNCHANGED");
+
+        self.metadata_admin.extended.set(&new);
+        self.internal_record_metadata_revision(fields);
+    }
+}
+
+#[near]
+impl Contract {
+    pub fn metadata_add_manager(&mut self, account_id: AccountId) {
+        self.assert_owner();
+        self.metadata_admin.managers.insert(&account_id);
+    }
+
+    pub fn metadata_remove_manager(&mut self, account_id: AccountId) {
+        self.assert_owner();
+        self.metadata_admin.managers.remove(&account_id);
+    }
+
+    /// Replaces the metadata, which must keep the current symbol and decimals.
+    pub fn ft_set_metadata(&mut self, metadata: FungibleTokenMetadata) {
+        self.internal_update_metadata(|current| *current = metadata);
+    }
+
+    /// Points the metadata to a new off-chain document. `reference_hash` is the base64-encoded
+    /// sha256 of its content.
+    pub fn ft_set_reference(
+        &mut self,
+        reference: Option<String>,
+        reference_hash: Option<Base64VecU8>,
+    ) {
+        self.internal_update_metadata(|current| {
+            current.reference = reference;
+            current.reference_hash = reference_hash;
+        });
+    }
+
+    pub fn ft_set_icon(&mut self, icon: Option<String>) {
+        self.internal_update_metadata(|current| current.icon = icon);
+    }
+
+    /// Replaces the extended metadata.
+    pub fn ft_set_extended_metadata(&mut self, extended: ExtendedMetadata) {
+        self.internal_update_extended_metadata(|current| *current = extended);
+    }
+
+    /// Sets the profile URL of a social `network`, or removes it when `url` is `None`.
+    pub fn ft_set_social(&mut self, network: String, url: Option<String>) {
+        self.internal_update_extended_metadata(|current| match url {
+            Some(url) => {
+                current.socials.insert(network, url);
+            }
+            None => {
+                current.socials.remove(&network);
+            }
+        });
+    }
+
+    pub fn ft_extended_metadata(&self) -> ExtendedMetadata {
+        self.metadata_admin.extended.get().unwrap_or_default()
+    }
+
+    /// Returns the metadata revisions, oldest first.
+    pub fn ft_metadata_history(
+        &self,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> Vec<MetadataRevision> {
+        self.metadata_admin
+            .history
+            .iter()
+            .skip(from_index.unwrap_or(0) as usize)
+            .take(limit.unwrap_or(u64::MAX) as usize)
+            .collect()
+    }
+
+    pub fn metadata_managers(&self, from_index: Option<u64>, limit: Option<u64>) -> Vec<AccountId> {
+        self.metadata_admin
+            .managers
+            .iter()
+            .skip(from_index.unwrap_or(0) as usize)
+            .take(limit.unwrap_or(u64::MAX) as usize)
+            .collect()
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_contract_standards::fungible_token::metadata::FungibleTokenMetadataProvider;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_utils::*;
+
+    fn manager() -> AccountId {
+        accounts(4)
+    }
+
+    fn setup_manager() -> (Contract, VMContextBuilder) {
+        let (mut contract, mut context) = setup();
+
+        testing_env!(context.predecessor_account_id(owner()).build());
+        contract.metadata_add_manager(manager());
+        testing_env!(context.predecessor_account_id(manager()).build());
+
+        (contract, context)
+    }
+
+    #[test]
+    fn test_set_reference_and_icon() {
+        let (mut contract, _) = setup_manager();
+
+        contract.ft_set_reference(
+            Some("https://intellex.xyz/metadata.json".to_string()),
+            Some(vec![1; 32].into()),
+        );
+        contract.ft_set_icon(None);
+
+        let metadata = contract.ft_metadata();
+        assert_eq!(
+            metadata.reference.as_deref(),
+            Some("https://intellex.xyz/metadata.json")
+        );
+        assert_eq!(metadata.reference_hash, Some(vec![1; 32].into()));
+        assert!(metadata.icon.is_none());
+        assert_eq!(metadata.symbol, "ITLX");
+    }
+
+    #[should_panic(expected = "Hash has to be 32 bytes")]
+    #[test]
+    fn test_set_reference_panics_on_invalid_hash() {
+        let (mut contract, _) = setup_manager();
+
+        contract.ft_set_reference(
+            Some("https://intellex.xyz".to_string()),
+            Some(vec![1; 8].into()),
+        );
+    }
+
+    #[should_panic(expected = "ERR_SYMBOL_IS_IMMUTABLE")]
+    #[test]
+    fn test_set_metadata_panics_on_symbol_change() {
+        let (mut contract, _) = setup_manager();
+
+        let mut metadata = contract.ft_metadata();
+        metadata.symbol = "XLTI".to_string();
+        contract.ft_set_metadata(metadata);
+    }
+
+    #[should_panic(expected = "ERR_DECIMALS_ARE_IMMUTABLE")]
+    #[test]
+    fn test_set_metadata_panics_on_decimals_change() {
+        let (mut contract, _) = setup_manager();
+
+        let mut metadata = contract.ft_metadata();
+        metadata.decimals = 18;
+        contract.ft_set_metadata(metadata);
+    }
+
+    #[test]
+    fn test_set_extended_metadata() {
+        let (mut contract, _) = setup_manager();
+        assert_eq!(contract.ft_extended_metadata(), ExtendedMetadata::default());
+
+        contract.ft_set_extended_metadata(ExtendedMetadata {
+            description: Some("Intellex AI Protocol".to_string()),
+            website: Some("https://intellex.xyz".to_string()),
+            audits: vec!["https://intellex.xyz/audit.pdf".to_string()],
+            ..Default::default()
+        });
+        contract.ft_set_social(
+            "twitter".to_string(),
+            Some("https://twitter.com/intellex_ai".to_string()),
+        );
+
+        let extended = contract.ft_extended_metadata();
+        assert_eq!(extended.website.as_deref(), Some("https://intellex.xyz"));
+        assert_eq!(extended.audits.len(), 1);
+        assert_eq!(
+            extended.socials.get("twitter").map(String::as_str),
+            Some("https://twitter.com/intellex_ai")
+        );
+
+        contract.ft_set_social("twitter".to_string(), None);
+        assert!(contract.ft_extended_metadata().socials.is_empty());
+    }
+
+    #[test]
+    fn test_metadata_history() {
+        let (mut contract, mut context) = setup_manager();
+
+        testing_env!(context.block_height(7).block_timestamp(70).build());
+        contract.ft_set_reference(
+            Some("https://intellex.xyz/metadata.json".to_string()),
+            Some(vec![1; 32].into()),
+        );
+        testing_env!(context.block_height(8).block_timestamp(80).build());
+        contract.ft_set_social(
+            "telegram".to_string(),
+            Some("https://t.me/intellex_ai".to_string()),
+        );
+
+        let history = contract.ft_metadata_history(None, None);
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].block_height.0, 7);
+        assert_eq!(history[0].timestamp.0, 70);
+        assert_eq!(history[0].updated_by, manager());
+        assert_eq!(history[0].fields, vec!["reference", "reference_hash"]);
+        assert_eq!(history[0].reference_hash, Some(vec![1; 32].into()));
+        assert_eq!(history[1].fields, vec!["socials"]);
+        assert_eq!(history[1].reference_hash, Some(vec![1; 32].into()));
+        assert_eq!(
+            contract.ft_metadata_history(Some(1), Some(5)),
+            vec![history[1].clone()]
+        );
+    }
+
+    #[should_panic(expected = "ERR_METADATA_UNCHANGED")]
+    #[test]
+    fn test_remove_missing_social_panics() {
+        let (mut contract, _) = setup_manager();
+
+        contract.ft_set_social("medium".to_string(), None);
+    }
+
+    #[should_panic(expected = "ERR_NOT_METADATA_MANAGER")]
+    #[test]
+    fn test_set_icon_panics_for_owner() {
+        let (mut contract, mut context) = setup();
+
+        testing_env!(context.predecessor_account_id(owner()).build());
+        contract.ft_set_icon(None);
+    }
+}
@@ -0,0 +1,289 @@
+//! Self-imposed limits on the outflows of an account.
+//!
+//! An account can cap what it sends to `amount` per rolling `window`, to contain the damage a
+//! leaked hot wallet key can do. The window is tracked as a leaky bucket: every outflow adds to
+//! the spent amount, which is released linearly over the window. Outflows are those of
+//! `ft_transfer`, `ft_transfer_call` and of the tokens locked into the features of the contract.
+//!
+//! A tighter limit takes effect immediately, but a looser one, including removing the limit,
+//! only after [`OUTFLOW_LIMIT_LOOSENING_DELAY`], so whoever stole the key cannot lift it first.
+use near_contract_standards::fungible_token::Balance;
+use near_sdk::collections::LookupMap;
+use near_sdk::json_types::{U128, U64};
+use near_sdk::{env, near, require, AccountId, Timestamp};
+
+use crate::internal::{assert_at_least_one_yocto, refund_deposit, refund_released_storage};
+use crate::{Contract, ContractExt, StorageKey};
+
+/// Delay before a looser limit takes effect, in nanoseconds.
+pub const OUTFLOW_LIMIT_LOOSENING_DELAY: u64 = 24 * 60 * 60 * 1_000_000_000;
+
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OutflowLimit {
+    pub amount: U128,
+    /// Length of the rolling window in nanoseconds.
+    pub window: U64,
+}
+
+impl OutflowLimit {
+    /// Whether `self` allows no more than `other`, both in a single burst and over time.
+    fn is_tighter_than(&self, other: &Option<OutflowLimit>) -> bool {
+        match other {
+            Some(other) => self.amount.0 <= other.amount.0 && self.window.0 >= other.window.0,
+            None => true,
+        }
+    }
+
+    fn released(&self, elapsed: u64) -> Balance {
+        let window = Balance::from(self.window.0);
+        let elapsed = Balance::from(elapsed);
+        if elapsed >= window {
+            return self.amount.0;
+        }
+        self.amount.0 / window * elapsed + self.amount.0 % window * elapsed / window
+    }
+}
+
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct PendingOutflowLimit {
+    pub limit: Option<OutflowLimit>,
+    pub effective_at: U64,
+}
+
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct OutflowAccount {
+    pub limit: Option<OutflowLimit>,
+    /// Outflows not yet released from the window, as of `updated_at`.
+    pub spent: U128,
+    pub updated_at: U64,
+    pub pending: Option<PendingOutflowLimit>,
+}
+
+impl OutflowAccount {
+    /// Releases the spent amount up to `now` and applies the pending limit once it is due.
+    fn settle(&mut self, now: Timestamp) {
+        if let Some(limit) = self.limit {
+            let released = limit.released(now.saturating_sub(self.updated_at.0));
+            self.spent = self.spent.0.saturating_sub(released).into();
+        }
+        self.updated_at = now.into();
+        if let Some(pending) = self.pending.take() {
+            if now >= pending.effective_at.0 {
+                self.limit = pending.limit;
+            } else {
+                self.pending = Some(pending);
+            }
+        }
+    }
+
+    fn available(&self) -> Option<Balance> {
+        self.limit
+            .map(|limit| limit.amount.0.saturating_sub(self.spent.0))
+    }
+}
+
+#[near]
+pub struct OutflowLimits {
+    accounts: LookupMap<AccountId, OutflowAccount>,
+}
+
+impl OutflowLimits {
+    pub fn new() -> Self {
+        Self {
+            accounts: LookupMap::new(StorageKey::OutflowLimits),
+        }
+    }
+
+    fn settled(&self, account_id: &AccountId) -> Option<OutflowAccount> {
+        self.accounts.get(account_id).map(|mut account| {
+            account.settle(env::block_timestamp());
+            account
+        })
+    }
+}
+
+impl Contract {
+    /// Counts `amount` against the outflow limit of `account_id`, if it has one.
+    pub(crate) fn internal_record_outflow(&mut self, account_id: &AccountId, amount: Balance) {
+        if let Some(mut account) = self.outflow_limits.settled(account_id) {
+            if let Some(available) = account.available() {
+                require!(amount <= available, "ERR_OUTFLOW_LIMIT_EXCEEDED");
+                account.spent.0 += amount;
+            }
+            self.outflow_limits.accounts.insert(account_id, &account);
+        }
+    }
+}
+
+#[near]
+impl Contract {
+    /// Sets the outflow limit of the predecessor, or removes it when `limit` is `None`. A tighter
+    /// limit applies immediately and cancels any pending change; a looser one is scheduled.
+    /// The attached deposit pays for the storage of the limit.
+    #[payable]
+    pub fn outflow_limit_set(&mut self, limit: Option<OutflowLimit>) -> OutflowAccount {
+        assert_at_least_one_yocto();
+        let initial_storage_usage = env::storage_usage();
+        let account_id = env::predecessor_account_id();
+        if let Some(limit) = limit {
+            require!(
+                limit.amount.0 > 0 && limit.window.0 > 0,
+                "ERR_ZERO_OUTFLOW_LIMIT"
+            );
+        }
+
+        let now = env::block_timestamp();
+        let mut account = self
+            .outflow_limits
+            .settled(&account_id)
+            .unwrap_or(OutflowAccount {
+                limit: None,
+                spent: 0.into(),
+                updated_at: now.into(),
+                pending: None,
+            });
+        match limit {
+            Some(limit) if limit.is_tighter_than(&account.limit) => {
+                account.limit = Some(limit);
+                account.pending = None;
+            }
+            _ => {
+                account.pending = Some(PendingOutflowLimit {
+                    limit,
+                    effective_at: (now + OUTFLOW_LIMIT_LOOSENING_DELAY).into(),
+                });
+            }
+        }
+
+        if account.limit.is_none() && account.pending.is_none() {
+            self.outflow_limits.accounts.remove(&account_id);
+        } else {
+            self.outflow_limits.accounts.insert(&account_id, &account);
+        }
+
+        refund_released_storage(&account_id, initial_storage_usage);
+        refund_deposit(initial_storage_usage);
+        account
+    }
+
+    pub fn outflow_limit_of(&self, account_id: AccountId) -> Option<OutflowAccount> {
+        self.outflow_limits.settled(&account_id)
+    }
+
+    /// Amount `account_id` can send right now, or `None` without a limit.
+    pub fn outflow_available(&self, account_id: AccountId) -> Option<U128> {
+        self.outflow_limits
+            .settled(&account_id)
+            .and_then(|account| account.available())
+            .map(U128)
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::VMContextBuilder;
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_utils::*;
+
+    const WINDOW: u64 = 1_000;
+
+    fn limit(amount: Balance, window: u64) -> Option<OutflowLimit> {
+        Some(OutflowLimit {
+            amount: amount.into(),
+            window: window.into(),
+        })
+    }
+
+    fn set_limit(
+        contract: &mut Contract,
+        context: &mut VMContextBuilder,
+        timestamp: Timestamp,
+        limit: Option<OutflowLimit>,
+    ) {
+        testing_env!(context
+            .predecessor_account_id(owner())
+            .block_timestamp(timestamp)
+            .attached_deposit(ONE_NEAR)
+            .build());
+        contract.outflow_limit_set(limit);
+    }
+
+    fn setup_limit() -> (Contract, VMContextBuilder) {
+        let (mut contract, mut context) = setup();
+        register(&mut contract, &mut context, user1());
+        set_limit(&mut contract, &mut context, 0, limit(100, WINDOW));
+
+        (contract, context)
+    }
+
+    #[test]
+    fn test_outflows_release_over_window() {
+        let (mut contract, mut context) = setup_limit();
+
+        transfer(&mut contract, &mut context, owner(), user1(), 100);
+        assert_eq!(contract.outflow_available(owner()).unwrap().0, 0);
+
+        testing_env!(context.block_timestamp(WINDOW / 4).build());
+        assert_eq!(contract.outflow_available(owner()).unwrap().0, 25);
+        transfer(&mut contract, &mut context, owner(), user1(), 25);
+
+        testing_env!(context.block_timestamp(2 * WINDOW).build());
+        assert_eq!(contract.outflow_available(owner()).unwrap().0, 100);
+    }
+
+    #[should_panic(expected = "ERR_OUTFLOW_LIMIT_EXCEEDED")]
+    #[test]
+    fn test_transfer_panics_above_limit() {
+        let (mut contract, mut context) = setup_limit();
+
+        transfer(&mut contract, &mut context, owner(), user1(), 60);
+        transfer(&mut contract, &mut context, owner(), user1(), 41);
+    }
+
+    #[test]
+    fn test_loosening_is_delayed() {
+        let (mut contract, mut context) = setup_limit();
+
+        set_limit(&mut contract, &mut context, 10, limit(1_000, WINDOW));
+        assert_eq!(contract.outflow_available(owner()).unwrap().0, 100);
+
+        testing_env!(context
+            .block_timestamp(10 + OUTFLOW_LIMIT_LOOSENING_DELAY)
+            .build());
+        assert_eq!(contract.outflow_available(owner()).unwrap().0, 1_000);
+
+        set_limit(
+            &mut contract,
+            &mut context,
+            20 + OUTFLOW_LIMIT_LOOSENING_DELAY,
+            None,
+        );
+        testing_env!(context
+            .block_timestamp(20 + 2 * OUTFLOW_LIMIT_LOOSENING_DELAY)
+            .build());
+        assert!(contract.outflow_available(owner()).is_none());
+        transfer(&mut contract, &mut context, owner(), user1(), 10_000);
+    }
+
+    #[test]
+    fn test_tightening_is_immediate() {
+        let (mut contract, mut context) = setup_limit();
+
+        set_limit(&mut contract, &mut context, 10, None);
+        assert!(contract
+            .outflow_limit_of(owner())
+            .unwrap()
+            .pending
+            .is_some());
+
+        set_limit(&mut contract, &mut context, 20, limit(50, WINDOW));
+        let account = contract.outflow_limit_of(owner()).unwrap();
+        assert_eq!(account.limit, limit(50, WINDOW));
+        assert!(account.pending.is_none());
+    }
+}
@@ -0,0 +1,346 @@
+//! Session escrow driven by the session vault.
+//!
+//! A user opens a session by locking tokens against a session id and the provider serving it.
+//! The session vault account reports the cumulative usage while the session runs and settles
+//! it, paying the provider for the usage and refunding the rest to the user. If the vault does
+//! not settle the session before it expires, anyone can refund the whole amount to the user.
+//! Every transition emits an event, so the history of a session can be rebuilt from the logs.
+use near_contract_standards::fungible_token::Balance;
+use near_sdk::collections::LookupMap;
+use near_sdk::json_types::{U128, U64};
+use near_sdk::{assert_one_yocto, env, near, require, AccountId};
+
+use crate::account_index::AccountIndex;
+use crate::events::Event;
+use crate::internal::{assert_at_least_one_yocto, refund_deposit, refund_released_storage};
+use crate::locks::LockBucket;
+use crate::{Contract, ContractExt, StorageKey};
+
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Session {
+    pub id: u64,
+    pub session_id: String,
+    pub user_id: AccountId,
+    pub provider_id: AccountId,
+    pub amount: U128,
+    /// Cumulative usage reported by the session vault.
+    pub used: U128,
+    /// Block timestamp in nanoseconds after which the session can be refunded to the user.
+    pub expires_at: U64,
+}
+
+#[near]
+pub struct Sessions {
+    sessions: LookupMap<u64, Session>,
+    ids: LookupMap<String, u64>,
+    by_account: AccountIndex,
+    next_id: u64,
+}
+
+impl Sessions {
+    pub fn new() -> Self {
+        Self {
+            sessions: LookupMap::new(StorageKey::Sessions),
+            ids: LookupMap::new(StorageKey::SessionIds),
+            by_account: AccountIndex::new(StorageKey::SessionsByAccount),
+            next_id: 0,
+        }
+    }
+
+    fn session(&self, session_id: &String) -> Session {
+        self.ids
+            .get(session_id)
+            .and_then(|id| self.sessions.get(&id))
+            .unwrap_or_else(|| env::panic_str("ERR_SESSION_NOT_FOUND"))
+    }
+
+    fn remove(&mut self, session: &Session) {
+        self.sessions.remove(&session.id);
+        self.ids.remove(&session.session_id);
+        self.by_account.remove(&session.user_id, session.id);
+        self.by_account.remove(&session.provider_id, session.id);
+    }
+}
+
+impl Contract {
+    fn assert_session_vault(&self) {
+        require!(
+            self.session_vault_id.as_ref() == Some(&env::predecessor_account_id()),
+            "ERR_NOT_SESSION_VAULT"
+        );
+    }
+
+    /// Removes the session and pays out the locked tokens.
+    fn internal_close_session(
+        &mut self,
+        session: &Session,
+        provider_amount: Balance,
+        user_refund: Balance,
+    ) {
+        let initial_storage_usage = env::storage_usage();
+        self.sessions.remove(session);
+        self.internal_unlock(
+            LockBucket::Session,
+            &session.user_id,
+            &session.provider_id,
+            provider_amount,
+            "session payment",
+        );
+        self.internal_unlock(
+            LockBucket::Session,
+            &session.user_id,
+            &session.user_id,
+            user_refund,
+            "session refund",
+        );
+        refund_released_storage(&session.user_id, initial_storage_usage);
+    }
+}
+
+#[near]
+impl Contract {
+    /// Locks `amount` tokens of the predecessor for the session `session_id` served by
+    /// `provider_id`. The attached deposit pays for the session storage.
+    #[payable]
+    pub fn session_open(
+        &mut self,
+        session_id: String,
+        provider_id: AccountId,
+        amount: U128,
+        expires_at: U64,
+    ) {
+        assert_at_least_one_yocto();
+        let initial_storage_usage = env::storage_usage();
+        let user_id = env::predecessor_account_id();
+        require!(self.session_vault_id.is_some(), "ERR_SESSION_VAULT_NOT_SET");
+        require!(!session_id.is_empty(), "ERR_EMPTY_SESSION_ID");
+        require!(
+            self.sessions.ids.get(&session_id).is_none(),
+            "ERR_SESSION_ALREADY_EXISTS"
+        );
+        require!(user_id != provider_id, "ERR_SESSION_WITH_SELF");
+        require!(amount.0 > 0, "ERR_ZERO_AMOUNT");
+        require!(
+            expires_at.0 > env::block_timestamp(),
+            "ERR_EXPIRY_IN_THE_PAST"
+        );
+
+        self.internal_lock(LockBucket::Session, &user_id, amount.0, "session");
+
+        let id = self.sessions.next_id;
+        self.sessions.next_id += 1;
+        let session = Session {
+            id,
+            session_id,
+            user_id,
+            provider_id,
+            amount,
+            used: 0.into(),
+            expires_at,
+        };
+        self.sessions.sessions.insert(&id, &session);
+        self.sessions.ids.insert(&session.session_id, &id);
+        self.sessions.by_account.insert(&session.user_id, id);
+        self.sessions.by_account.insert(&session.provider_id, id);
+
+        Event::SessionOpened {
+            session_id: &session.session_id,
+            user_id: &session.user_id,
+            provider_id: &session.provider_id,
+            amount,
+            expires_at,
+        }
+        .emit();
+
+        refund_deposit(initial_storage_usage);
+    }
+
+    /// Records the cumulative usage of a running session. Only the session vault can report it.
+    pub fn session_report_usage(&mut self, session_id: String, used: U128) {
+        self.assert_session_vault();
+        let mut session = self.sessions.session(&session_id);
+        require!(
+            env::block_timestamp() < session.expires_at.0,
+            "ERR_SESSION_EXPIRED"
+        );
+        require!(used.0 >= session.used.0, "ERR_USAGE_CANNOT_DECREASE");
+        require!(
+            used.0 <= session.amount.0,
+            "ERR_USAGE_EXCEEDS_SESSION_AMOUNT"
+        );
+
+        session.used = used;
+        self.sessions.sessions.insert(&session.id, &session);
+        Event::SessionUsageReported {
+            session_id: &session_id,
+            used,
+        }
+        .emit();
+    }
+
+    /// Pays the provider for the reported usage and refunds the rest to the user. Only the
+    /// session vault can settle, and only before the session expires.
+    pub fn session_settle(&mut self, session_id: String) {
+        self.assert_session_vault();
+        let session = self.sessions.session(&session_id);
+        require!(
+            env::block_timestamp() < session.expires_at.0,
+            "ERR_SESSION_EXPIRED"
+        );
+
+        let user_refund = session.amount.0 - session.used.0;
+        self.internal_close_session(&session, session.used.0, user_refund);
+        Event::SessionSettled {
+            session_id: &session_id,
+            provider_id: &session.provider_id,
+            provider_amount: session.used,
+            user_refund: user_refund.into(),
+        }
+        .emit();
+    }
+
+    /// Refunds the whole amount of an expired session the vault never settled. Can be called by
+    /// anyone.
+    #[payable]
+    pub fn session_timeout_refund(&mut self, session_id: String) {
+        assert_one_yocto();
+        let session = self.sessions.session(&session_id);
+        require!(
+            env::block_timestamp() >= session.expires_at.0,
+            "ERR_SESSION_NOT_EXPIRED"
+        );
+
+        self.internal_close_session(&session, 0, session.amount.0);
+        Event::SessionTimedOut {
+            session_id: &session_id,
+            user_id: &session.user_id,
+            refund: session.amount,
+        }
+        .emit();
+    }
+
+    pub fn session(&self, session_id: String) -> Option<Session> {
+        self.sessions
+            .ids
+            .get(&session_id)
+            .and_then(|id| self.sessions.sessions.get(&id))
+    }
+
+    /// Lists the open sessions `account_id` is the user or the provider of.
+    pub fn sessions_of(
+        &self,
+        account_id: AccountId,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> Vec<Session> {
+        self.sessions
+            .by_account
+            .ids(&account_id, from_index, limit)
+            .into_iter()
+            .filter_map(|id| self.sessions.sessions.get(&id))
+            .collect()
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_contract_standards::fungible_token::FungibleTokenCore;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, Timestamp};
+
+    use super::*;
+    use crate::test_utils::*;
+
+    const AMOUNT: Balance = 1_000;
+    const EXPIRES_AT: Timestamp = 1_000;
+
+    fn vault() -> AccountId {
+        accounts(4)
+    }
+
+    fn session_id() -> String {
+        "session-1".to_string()
+    }
+
+    fn setup_session() -> (Contract, VMContextBuilder) {
+        let (mut contract, mut context) = setup();
+        register(&mut contract, &mut context, user1());
+
+        testing_env!(context
+            .block_timestamp(0)
+            .predecessor_account_id(owner())
+            .attached_deposit(ONE_NEAR)
+            .build());
+        contract.set_session_vault_id(vault());
+        contract.session_open(session_id(), user1(), AMOUNT.into(), EXPIRES_AT.into());
+
+        (contract, context)
+    }
+
+    #[test]
+    fn test_settle_pays_reported_usage() {
+        let (mut contract, mut context) = setup_session();
+        assert_eq!(contract.ft_balance_of(owner()).0, TOTAL_SUPPLY - AMOUNT);
+
+        at(&mut context, 10, vault());
+        contract.session_report_usage(session_id(), 100.into());
+        contract.session_report_usage(session_id(), 350.into());
+        assert_eq!(contract.session(session_id()).unwrap().used.0, 350);
+
+        contract.session_settle(session_id());
+        assert_eq!(contract.ft_balance_of(user1()).0, 350);
+        assert_eq!(contract.ft_balance_of(owner()).0, TOTAL_SUPPLY - 350);
+        assert!(contract.session(session_id()).is_none());
+        assert!(contract.sessions_of(user1(), None, None).is_empty());
+    }
+
+    #[should_panic(expected = "ERR_NOT_SESSION_VAULT")]
+    #[test]
+    fn test_report_usage_panics_for_provider() {
+        let (mut contract, mut context) = setup_session();
+
+        at(&mut context, 10, user1());
+        contract.session_report_usage(session_id(), 100.into());
+    }
+
+    #[should_panic(expected = "ERR_USAGE_CANNOT_DECREASE")]
+    #[test]
+    fn test_report_usage_panics_when_decreasing() {
+        let (mut contract, mut context) = setup_session();
+
+        at(&mut context, 10, vault());
+        contract.session_report_usage(session_id(), 100.into());
+        contract.session_report_usage(session_id(), 50.into());
+    }
+
+    #[should_panic(expected = "ERR_SESSION_ALREADY_EXISTS")]
+    #[test]
+    fn test_open_panics_on_duplicate_id() {
+        let (mut contract, _) = setup_session();
+
+        contract.session_open(session_id(), user1(), AMOUNT.into(), EXPIRES_AT.into());
+    }
+
+    #[test]
+    fn test_timeout_refunds_user() {
+        let (mut contract, mut context) = setup_session();
+
+        at(&mut context, 10, vault());
+        contract.session_report_usage(session_id(), 100.into());
+
+        at(&mut context, EXPIRES_AT, user2());
+        contract.session_timeout_refund(session_id());
+        assert_eq!(contract.ft_balance_of(owner()).0, TOTAL_SUPPLY);
+        assert_eq!(contract.locks.bucket(LockBucket::Session), 0);
+    }
+
+    #[should_panic(expected = "ERR_SESSION_EXPIRED")]
+    #[test]
+    fn test_settle_panics_after_expiry() {
+        let (mut contract, mut context) = setup_session();
+
+        at(&mut context, EXPIRES_AT, vault());
+        contract.session_settle(session_id());
+    }
+}
@@ -0,0 +1,216 @@
+//! Activity statistics of the registered accounts.
+//!
+//! Every account gets a statistics entry at registration, paid as part of the storage returned
+//! by `storage_balance_bounds`, and loses it when it unregisters.
+//!
+//! A transfer counts as a send for one side and a receive for the other. Transfers are those of
+//! `ft_transfer` and `ft_transfer_call`, net of the transfer fee, the fee itself as a transfer to
+//! the treasury, the refunds of `ft_transfer_call` and the payouts of the features of the
+//! contract, counted from the account that locked the tokens to the recipient. The legs that only
+//! move tokens to or from the contract account are not counted, nor are refunds of locked tokens
+//! to the account that locked them, seizures, mints and burns.
+use near_contract_standards::fungible_token::Balance;
+use near_sdk::collections::LookupMap;
+use near_sdk::json_types::{U128, U64};
+use near_sdk::{env, near, AccountId, StorageUsage};
+
+use crate::{Contract, ContractExt, StorageKey};
+
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct AccountStats {
+    pub registered_at: U64,
+    pub last_transfer_at: Option<U64>,
+    pub sends: u64,
+    pub receives: u64,
+    pub sent: U128,
+    pub received: U128,
+}
+
+impl AccountStats {
+    fn new(registered_at: u64) -> Self {
+        Self {
+            registered_at: registered_at.into(),
+            last_transfer_at: None,
+            sends: 0,
+            receives: 0,
+            sent: 0.into(),
+            received: 0.into(),
+        }
+    }
+}
+
+#[near]
+pub struct Stats {
+    accounts: LookupMap<AccountId, AccountStats>,
+}
+
+impl Stats {
+    pub fn new() -> Self {
+        Self {
+            accounts: LookupMap::new(StorageKey::AccountStats),
+        }
+    }
+
+    /// Storage used by the statistics of an account with the longest possible id.
+    pub fn measure_account_storage_usage(&mut self) -> StorageUsage {
+        let initial_storage_usage = env::storage_usage();
+        let tmp_account_id: AccountId = "a".repeat(64).parse().unwrap();
+        let mut stats = AccountStats::new(0);
+        stats.last_transfer_at = Some(0.into());
+        self.accounts.insert(&tmp_account_id, &stats);
+        let account_storage_usage = env::storage_usage() - initial_storage_usage;
+        self.accounts.remove(&tmp_account_id);
+        account_storage_usage
+    }
+
+    pub fn insert(&mut self, account_id: &AccountId) {
+        self.accounts
+            .insert(account_id, &AccountStats::new(env::block_timestamp()));
+    }
+
+    pub fn remove(&mut self, account_id: &AccountId) {
+        self.accounts.remove(account_id);
+    }
+
+    fn update(&mut self, account_id: &AccountId, update: impl FnOnce(&mut AccountStats)) {
+        if let Some(mut stats) = self.accounts.get(account_id) {
+            stats.last_transfer_at = Some(env::block_timestamp().into());
+            update(&mut stats);
+            self.accounts.insert(account_id, &stats);
+        }
+    }
+}
+
+impl Contract {
+    pub(crate) fn internal_record_transfer_stats(
+        &mut self,
+        from: &AccountId,
+        to: &AccountId,
+        amount: Balance,
+    ) {
+        if amount == 0 || from == to {
+            return;
+        }
+        self.stats.update(from, |stats| {
+            stats.sends += 1;
+            stats.sent.0 = stats.sent.0.saturating_add(amount);
+        });
+        self.stats.update(to, |stats| {
+            stats.receives += 1;
+            stats.received.0 = stats.received.0.saturating_add(amount);
+        });
+    }
+}
+
+#[near]
+impl Contract {
+    pub fn ft_account_stats(&self, account_id: AccountId) -> Option<AccountStats> {
+        self.stats.accounts.get(&account_id)
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_contract_standards::fungible_token::{FungibleTokenCore, FungibleTokenResolver};
+    use near_contract_standards::storage_management::StorageManagement;
+    use near_sdk::json_types::{U128, U64};
+    use near_sdk::{testing_env, PromiseResult};
+
+    use crate::test_utils::*;
+
+    #[test]
+    fn test_stats_count_transfers() {
+        let (mut contract, mut context) = setup();
+        testing_env!(context.block_timestamp(10).build());
+        register(&mut contract, &mut context, user1());
+
+        testing_env!(context.block_timestamp(20).build());
+        transfer(&mut contract, &mut context, owner(), user1(), 100);
+        transfer(&mut contract, &mut context, user1(), owner(), 30);
+
+        let stats = contract.ft_account_stats(user1()).unwrap();
+        assert_eq!(stats.registered_at.0, 10);
+        assert_eq!(stats.last_transfer_at, Some(20.into()));
+        assert_eq!((stats.sends, stats.receives), (1, 1));
+        assert_eq!((stats.sent.0, stats.received.0), (30, 100));
+
+        let stats = contract.ft_account_stats(owner()).unwrap();
+        assert_eq!((stats.sends, stats.receives), (1, 1));
+        assert!(contract.ft_account_stats(user2()).is_none());
+    }
+
+    #[test]
+    fn test_stats_count_transfer_call_once() {
+        let (mut contract, mut context) = setup();
+        register(&mut contract, &mut context, user1());
+
+        testing_env!(context
+            .predecessor_account_id(owner())
+            .attached_deposit(ONE_YOCTO)
+            .block_timestamp(10)
+            .build());
+        let _ = contract.ft_transfer_call(user1(), 100.into(), None, String::new());
+
+        // the receiver used everything: nothing is refunded and nothing more is counted
+        testing_env!(
+            context
+                .predecessor_account_id(current())
+                .block_timestamp(20)
+                .build(),
+            near_sdk::test_vm_config(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Successful(
+                near_sdk::serde_json::to_vec(&U128(0)).unwrap()
+            )],
+        );
+        assert_eq!(
+            contract.ft_resolve_transfer(owner(), user1(), 100.into()).0,
+            100
+        );
+
+        let stats = contract.ft_account_stats(user1()).unwrap();
+        assert_eq!((stats.sends, stats.receives), (0, 1));
+        assert_eq!(stats.last_transfer_at, Some(U64(10)));
+        let stats = contract.ft_account_stats(owner()).unwrap();
+        assert_eq!((stats.sends, stats.receives), (1, 0));
+    }
+
+    #[test]
+    fn test_stats_count_payouts_from_locker() {
+        let (mut contract, mut context) = setup();
+        register(&mut contract, &mut context, user1());
+
+        testing_env!(context
+            .predecessor_account_id(owner())
+            .attached_deposit(ONE_NEAR)
+            .build());
+        contract.escrow_create(user1(), 100.into(), user2(), U64(1));
+        assert_eq!(contract.ft_account_stats(owner()).unwrap().sends, 0);
+
+        testing_env!(context.attached_deposit(ONE_YOCTO).build());
+        contract.escrow_release(0);
+
+        let stats = contract.ft_account_stats(owner()).unwrap();
+        assert_eq!((stats.sends, stats.sent.0), (1, 100));
+        let stats = contract.ft_account_stats(user1()).unwrap();
+        assert_eq!((stats.receives, stats.received.0), (1, 100));
+        let stats = contract.ft_account_stats(current()).unwrap();
+        assert_eq!((stats.sends, stats.receives), (0, 0));
+    }
+
+    #[test]
+    fn test_stats_removed_on_unregister() {
+        let (mut contract, mut context) = setup();
+        register(&mut contract, &mut context, user1());
+        assert!(contract.ft_account_stats(user1()).is_some());
+
+        testing_env!(context
+            .predecessor_account_id(user1())
+            .attached_deposit(ONE_YOCTO)
+            .build());
+        contract.storage_unregister(None);
+        assert!(contract.ft_account_stats(user1()).is_none());
+    }
+}
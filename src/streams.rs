@@ -0,0 +1,393 @@
+//! Continuous payment streams.
+//!
+//! The sender locks `rate_per_second` times the duration of the stream up front, and the receiver
+//! can withdraw whatever has streamed so far at any time. Either party can cancel: the receiver
+//! gets what has streamed and not been withdrawn yet, the sender gets the rest back. The sender
+//! pays for the storage of the stream and is refunded once it is removed.
+use near_contract_standards::fungible_token::Balance;
+use near_sdk::collections::LookupMap;
+use near_sdk::json_types::{U128, U64};
+use near_sdk::{assert_one_yocto, env, near, require, AccountId, Timestamp};
+
+use crate::account_index::AccountIndex;
+use crate::events::Event;
+use crate::internal::{assert_at_least_one_yocto, refund_deposit, refund_released_storage};
+use crate::locks::LockBucket;
+use crate::{Contract, ContractExt, StorageKey};
+
+const NANOS_PER_SECOND: u64 = 1_000_000_000;
+
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Stream {
+    pub id: u64,
+    pub sender_id: AccountId,
+    pub receiver_id: AccountId,
+    pub rate_per_second: U128,
+    /// Block timestamps in nanoseconds.
+    pub start: U64,
+    pub end: U64,
+    pub deposited: U128,
+    pub withdrawn: U128,
+}
+
+impl Stream {
+    /// Amount streamed to the receiver by `timestamp`, counted in whole seconds.
+    pub fn streamed_at(&self, timestamp: Timestamp) -> Balance {
+        if timestamp >= self.end.0 {
+            return self.deposited.0;
+        }
+        let elapsed_seconds = timestamp.saturating_sub(self.start.0) / NANOS_PER_SECOND;
+        std::cmp::min(
+            self.deposited.0,
+            self.rate_per_second.0 * Balance::from(elapsed_seconds),
+        )
+    }
+
+    fn withdrawable_at(&self, timestamp: Timestamp) -> Balance {
+        self.streamed_at(timestamp) - self.withdrawn.0
+    }
+}
+
+#[near]
+pub struct Streams {
+    streams: LookupMap<u64, Stream>,
+    by_account: AccountIndex,
+    next_id: u64,
+}
+
+impl Streams {
+    pub fn new() -> Self {
+        Self {
+            streams: LookupMap::new(StorageKey::Streams),
+            by_account: AccountIndex::new(StorageKey::StreamsByAccount),
+            next_id: 0,
+        }
+    }
+
+    fn stream(&self, stream_id: u64) -> Stream {
+        self.streams
+            .get(&stream_id)
+            .unwrap_or_else(|| env::panic_str("ERR_STREAM_NOT_FOUND"))
+    }
+
+    fn remove(&mut self, stream: &Stream) {
+        self.streams.remove(&stream.id);
+        self.by_account.remove(&stream.sender_id, stream.id);
+        self.by_account.remove(&stream.receiver_id, stream.id);
+    }
+}
+
+#[near]
+impl Contract {
+    /// Streams `rate_per_second` tokens of the predecessor to the registered `receiver_id` from
+    /// `start` (defaults to now, cannot be in the past) until `end`. The attached deposit pays for
+    /// the stream storage.
+    #[payable]
+    pub fn stream_create(
+        &mut self,
+        receiver_id: AccountId,
+        rate_per_second: U128,
+        start: Option<U64>,
+        end: U64,
+    ) -> u64 {
+        assert_at_least_one_yocto();
+        let initial_storage_usage = env::storage_usage();
+        let sender_id = env::predecessor_account_id();
+        let start = start.map_or_else(env::block_timestamp, |start| start.0);
+        require!(sender_id != receiver_id, "ERR_STREAM_TO_SELF");
+        require!(
+            self.token.accounts.contains_key(&receiver_id),
+            "ERR_RECEIVER_NOT_REGISTERED"
+        );
+        require!(rate_per_second.0 > 0, "ERR_ZERO_RATE");
+        require!(start >= env::block_timestamp(), "ERR_STREAM_START_IN_PAST");
+        require!(
+            start
+                .checked_add(NANOS_PER_SECOND)
+                .is_some_and(|min_end| end.0 >= min_end),
+            "ERR_STREAM_SHORTER_THAN_A_SECOND"
+        );
+
+        let duration_seconds = (end.0 - start) / NANOS_PER_SECOND;
+        let deposited = rate_per_second
+            .0
+            .checked_mul(Balance::from(duration_seconds))
+            .unwrap_or_else(|| env::panic_str("ERR_STREAM_AMOUNT_OVERFLOW"));
+        self.internal_lock(LockBucket::Stream, &sender_id, deposited, "stream deposit");
+
+        let id = self.streams.next_id;
+        self.streams.next_id += 1;
+        let stream = Stream {
+            id,
+            sender_id,
+            receiver_id,
+            rate_per_second,
+            start: start.into(),
+            end,
+            deposited: deposited.into(),
+            withdrawn: 0.into(),
+        };
+        self.streams.streams.insert(&id, &stream);
+        self.streams.by_account.insert(&stream.sender_id, id);
+        self.streams.by_account.insert(&stream.receiver_id, id);
+
+        Event::StreamCreated {
+            stream_id: id,
+            sender_id: &stream.sender_id,
+            receiver_id: &stream.receiver_id,
+            rate_per_second,
+            start: stream.start,
+            end,
+        }
+        .emit();
+
+        refund_deposit(initial_storage_usage);
+        id
+    }
+
+    /// Withdraws `amount` (defaults to everything available) of the streamed tokens to the
+    /// receiver. A finished stream is removed once fully withdrawn.
+    #[payable]
+    pub fn stream_withdraw(&mut self, stream_id: u64, amount: Option<U128>) -> U128 {
+        assert_one_yocto();
+        let initial_storage_usage = env::storage_usage();
+        let mut stream = self.streams.stream(stream_id);
+        require!(
+            env::predecessor_account_id() == stream.receiver_id,
+            "ERR_NOT_STREAM_RECEIVER"
+        );
+
+        let withdrawable = stream.withdrawable_at(env::block_timestamp());
+        let amount = amount.map_or(withdrawable, |amount| amount.0);
+        require!(amount > 0, "ERR_NOTHING_TO_WITHDRAW");
+        require!(amount <= withdrawable, "ERR_AMOUNT_NOT_STREAMED_YET");
+
+        stream.withdrawn.0 += amount;
+        if stream.withdrawn == stream.deposited {
+            self.streams.remove(&stream);
+        } else {
+            self.streams.streams.insert(&stream_id, &stream);
+        }
+
+        self.internal_unlock(
+            LockBucket::Stream,
+            &stream.sender_id,
+            &stream.receiver_id,
+            amount,
+            "stream withdrawal",
+        );
+        Event::StreamWithdrawn {
+            stream_id,
+            receiver_id: &stream.receiver_id,
+            amount: amount.into(),
+        }
+        .emit();
+
+        refund_released_storage(&stream.sender_id, initial_storage_usage);
+        amount.into()
+    }
+
+    /// Stops the stream, paying the receiver what has streamed and refunding the sender the
+    /// rest. Can be called by either party.
+    #[payable]
+    pub fn stream_cancel(&mut self, stream_id: u64) {
+        assert_one_yocto();
+        let initial_storage_usage = env::storage_usage();
+        let stream = self.streams.stream(stream_id);
+        let predecessor_account_id = env::predecessor_account_id();
+        require!(
+            predecessor_account_id == stream.sender_id
+                || predecessor_account_id == stream.receiver_id,
+            "ERR_NOT_STREAM_PARTY"
+        );
+
+        let streamed = stream.streamed_at(env::block_timestamp());
+        let receiver_amount = streamed - stream.withdrawn.0;
+        let sender_refund = stream.deposited.0 - streamed;
+        self.streams.remove(&stream);
+
+        self.internal_unlock(
+            LockBucket::Stream,
+            &stream.sender_id,
+            &stream.receiver_id,
+            receiver_amount,
+            "stream withdrawal",
+        );
+        self.internal_unlock(
+            LockBucket::Stream,
+            &stream.sender_id,
+            &stream.sender_id,
+            sender_refund,
+            "stream refund",
+        );
+        Event::StreamCanceled {
+            stream_id,
+            receiver_amount: receiver_amount.into(),
+            sender_refund: sender_refund.into(),
+        }
+        .emit();
+
+        refund_released_storage(&stream.sender_id, initial_storage_usage);
+    }
+
+    pub fn stream(&self, stream_id: u64) -> Option<Stream> {
+        self.streams.streams.get(&stream_id)
+    }
+
+    pub fn stream_streamed_amount(&self, stream_id: u64) -> U128 {
+        self.streams
+            .stream(stream_id)
+            .streamed_at(env::block_timestamp())
+            .into()
+    }
+
+    pub fn stream_withdrawable_amount(&self, stream_id: u64) -> U128 {
+        self.streams
+            .stream(stream_id)
+            .withdrawable_at(env::block_timestamp())
+            .into()
+    }
+
+    /// Lists the streams `account_id` sends or receives.
+    pub fn streams_of(
+        &self,
+        account_id: AccountId,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> Vec<Stream> {
+        self.streams
+            .by_account
+            .ids(&account_id, from_index, limit)
+            .into_iter()
+            .filter_map(|id| self.streams.streams.get(&id))
+            .collect()
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_contract_standards::fungible_token::FungibleTokenCore;
+    use near_sdk::test_utils::VMContextBuilder;
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_utils::*;
+
+    const RATE: Balance = 10;
+    const START: Timestamp = 100 * NANOS_PER_SECOND;
+    const END: Timestamp = 200 * NANOS_PER_SECOND;
+
+    fn setup_stream() -> (Contract, VMContextBuilder) {
+        let (mut contract, mut context) = setup();
+        register(&mut contract, &mut context, user1());
+
+        testing_env!(context
+            .block_timestamp(0)
+            .predecessor_account_id(owner())
+            .attached_deposit(ONE_NEAR)
+            .build());
+        contract.stream_create(user1(), RATE.into(), Some(START.into()), END.into());
+
+        (contract, context)
+    }
+
+    #[test]
+    fn test_create_locks_deposit() {
+        let (contract, _) = setup_stream();
+
+        assert_eq!(contract.ft_balance_of(owner()).0, TOTAL_SUPPLY - 1_000);
+        assert_eq!(contract.stream(0).unwrap().deposited.0, 1_000);
+        assert_eq!(contract.stream_streamed_amount(0).0, 0);
+        assert_eq!(contract.streams_of(owner(), None, None).len(), 1);
+        assert_eq!(contract.streams_of(user1(), None, None).len(), 1);
+    }
+
+    #[should_panic(expected = "ERR_RECEIVER_NOT_REGISTERED")]
+    #[test]
+    fn test_create_panics_for_unregistered_receiver() {
+        let (mut contract, mut context) = setup_stream();
+
+        at(&mut context, 0, owner());
+        contract.stream_create(user2(), RATE.into(), None, END.into());
+    }
+
+    #[should_panic(expected = "ERR_STREAM_START_IN_PAST")]
+    #[test]
+    fn test_create_panics_for_start_in_past() {
+        let (mut contract, mut context) = setup_stream();
+
+        at(&mut context, START, owner());
+        contract.stream_create(user1(), RATE.into(), Some((START - 1).into()), END.into());
+    }
+
+    #[should_panic(expected = "ERR_STREAM_SHORTER_THAN_A_SECOND")]
+    #[test]
+    fn test_create_panics_for_overflowing_start() {
+        let (mut contract, mut context) = setup_stream();
+
+        at(&mut context, 0, owner());
+        contract.stream_create(user1(), RATE.into(), Some(u64::MAX.into()), u64::MAX.into());
+    }
+
+    #[test]
+    fn test_withdraw_streamed_amount() {
+        let (mut contract, mut context) = setup_stream();
+
+        at(&mut context, START + 30 * NANOS_PER_SECOND + 1, user1());
+        assert_eq!(contract.stream_withdrawable_amount(0).0, 300);
+        assert_eq!(contract.stream_withdraw(0, None).0, 300);
+        assert_eq!(contract.ft_balance_of(user1()).0, 300);
+
+        at(&mut context, END + 1, user1());
+        assert_eq!(contract.stream_withdraw(0, None).0, 700);
+        assert_eq!(contract.ft_balance_of(user1()).0, 1_000);
+
+        // fully withdrawn streams are removed
+        assert!(contract.stream(0).is_none());
+        assert!(contract.streams_of(owner(), None, None).is_empty());
+    }
+
+    #[should_panic(expected = "ERR_AMOUNT_NOT_STREAMED_YET")]
+    #[test]
+    fn test_withdraw_panics_above_streamed_amount() {
+        let (mut contract, mut context) = setup_stream();
+
+        at(&mut context, START + 10 * NANOS_PER_SECOND, user1());
+        contract.stream_withdraw(0, Some(101.into()));
+    }
+
+    #[should_panic(expected = "ERR_NOT_STREAM_RECEIVER")]
+    #[test]
+    fn test_withdraw_panics_for_sender() {
+        let (mut contract, mut context) = setup_stream();
+
+        at(&mut context, END, owner());
+        contract.stream_withdraw(0, None);
+    }
+
+    #[test]
+    fn test_cancel_splits_balances() {
+        let (mut contract, mut context) = setup_stream();
+
+        at(&mut context, START + 20 * NANOS_PER_SECOND, user1());
+        contract.stream_withdraw(0, Some(50.into()));
+
+        at(&mut context, START + 40 * NANOS_PER_SECOND, owner());
+        contract.stream_cancel(0);
+
+        assert_eq!(contract.ft_balance_of(user1()).0, 400);
+        assert_eq!(contract.ft_balance_of(owner()).0, TOTAL_SUPPLY - 400);
+        assert_eq!(contract.locks.bucket(LockBucket::Stream), 0);
+        assert!(contract.stream(0).is_none());
+    }
+
+    #[should_panic(expected = "ERR_NOT_STREAM_PARTY")]
+    #[test]
+    fn test_cancel_panics_for_third_party() {
+        let (mut contract, mut context) = setup_stream();
+
+        at(&mut context, START, user2());
+        contract.stream_cancel(0);
+    }
+}
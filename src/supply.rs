@@ -0,0 +1,130 @@
+//! Circulating supply for market data aggregators.
+//!
+//! The circulating supply is the total supply minus the balances of the non-circulating accounts
+//! maintained by the owner, such as the treasury or vesting wallets, and minus the tokens locked
+//! into the features of the contract. When the contract account itself is listed, only its
+//! balance above the locked amount is subtracted, so locked tokens are not counted twice.
+use near_contract_standards::fungible_token::Balance;
+use near_sdk::collections::UnorderedSet;
+use near_sdk::json_types::U128;
+use near_sdk::{env, near, require, AccountId};
+
+use crate::{Contract, ContractExt, StorageKey};
+
+/// Maximum number of non-circulating accounts, which bounds the gas of `ft_circulating_supply`.
+pub const MAX_NON_CIRCULATING_ACCOUNTS: u64 = 100;
+
+#[near]
+pub struct Supply {
+    non_circulating: UnorderedSet<AccountId>,
+}
+
+impl Supply {
+    pub fn new() -> Self {
+        Self {
+            non_circulating: UnorderedSet::new(StorageKey::NonCirculatingAccounts),
+        }
+    }
+}
+
+impl Contract {
+    fn internal_non_circulating_supply(&self) -> Balance {
+        let contract_id = env::current_account_id();
+        let locked = self.locks.total();
+        self.supply
+            .non_circulating
+            .iter()
+            .map(|account_id| {
+                let balance = self.token.accounts.get(&account_id).unwrap_or(0);
+                if account_id == contract_id {
+                    balance.saturating_sub(locked)
+                } else {
+                    balance
+                }
+            })
+            .fold(locked, Balance::saturating_add)
+    }
+}
+
+#[near]
+impl Contract {
+    pub fn supply_add_non_circulating_accounts(&mut self, account_ids: Vec<AccountId>) {
+        self.assert_owner();
+        for account_id in account_ids.iter() {
+            self.supply.non_circulating.insert(account_id);
+        }
+        require!(
+            self.supply.non_circulating.len() <= MAX_NON_CIRCULATING_ACCOUNTS,
+            "ERR_TOO_MANY_NON_CIRCULATING_ACCOUNTS"
+        );
+    }
+
+    pub fn supply_remove_non_circulating_accounts(&mut self, account_ids: Vec<AccountId>) {
+        self.assert_owner();
+        for account_id in account_ids.iter() {
+            self.supply.non_circulating.remove(account_id);
+        }
+    }
+
+    pub fn supply_non_circulating_accounts(
+        &self,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> Vec<AccountId> {
+        self.supply
+            .non_circulating
+            .iter()
+            .skip(from_index.unwrap_or(0) as usize)
+            .take(limit.unwrap_or(u64::MAX) as usize)
+            .collect()
+    }
+
+    /// Returns `ft_total_supply` minus the non-circulating balances and the locked tokens.
+    pub fn ft_circulating_supply(&self) -> U128 {
+        self.token
+            .total_supply
+            .saturating_sub(self.internal_non_circulating_supply())
+            .into()
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::json_types::U64;
+    use near_sdk::testing_env;
+
+    use crate::test_utils::*;
+
+    #[test]
+    fn test_circulating_supply() {
+        let (mut contract, mut context) = setup();
+        register(&mut contract, &mut context, user1());
+        transfer(&mut contract, &mut context, owner(), user1(), 1_000);
+        assert_eq!(contract.ft_circulating_supply().0, TOTAL_SUPPLY);
+
+        testing_env!(context.predecessor_account_id(owner()).build());
+        contract.supply_add_non_circulating_accounts(vec![owner()]);
+        assert_eq!(contract.ft_circulating_supply().0, 1_000);
+
+        testing_env!(context
+            .predecessor_account_id(user1())
+            .attached_deposit(ONE_NEAR)
+            .build());
+        contract.escrow_create(user2(), 100.into(), owner(), U64(1));
+        assert_eq!(contract.ft_circulating_supply().0, 900);
+
+        transfer(&mut contract, &mut context, owner(), current(), 50);
+        testing_env!(context.predecessor_account_id(owner()).build());
+        contract.supply_add_non_circulating_accounts(vec![current()]);
+        assert_eq!(contract.ft_circulating_supply().0, 900);
+    }
+
+    #[should_panic(expected = "ERR_NOT_OWNER")]
+    #[test]
+    fn test_add_non_circulating_accounts_panics_for_non_owner() {
+        let (mut contract, mut context) = setup();
+
+        testing_env!(context.predecessor_account_id(user1()).build());
+        contract.supply_add_non_circulating_accounts(vec![user1()]);
+    }
+}
@@ -0,0 +1,84 @@
+//! Fixtures shared by the unit tests of the contract modules.
+use near_contract_standards::fungible_token::{Balance, FungibleTokenCore};
+use near_contract_standards::storage_management::StorageManagement;
+use near_sdk::test_utils::{accounts, VMContextBuilder};
+use near_sdk::{env, testing_env, AccountId, NearToken, Timestamp};
+
+use crate::Contract;
+
+pub const TOTAL_SUPPLY: Balance = 1_000_000_000_000_000;
+pub const ONE_NEAR: NearToken = NearToken::from_near(1);
+pub const ONE_YOCTO: NearToken = NearToken::from_yoctonear(1);
+
+pub fn current() -> AccountId {
+    accounts(0)
+}
+
+pub fn owner() -> AccountId {
+    accounts(1)
+}
+
+pub fn user1() -> AccountId {
+    accounts(2)
+}
+
+pub fn user2() -> AccountId {
+    accounts(3)
+}
+
+/// Deploys the contract with `owner()` as both the owner and the holder of the total supply.
+pub fn setup() -> (Contract, VMContextBuilder) {
+    let mut context = VMContextBuilder::new();
+    testing_env!(context.signer_account_id(owner()).build());
+
+    let contract = Contract::new_default_meta(owner(), TOTAL_SUPPLY.into());
+
+    context.storage_usage(env::storage_usage());
+    context.current_account_id(current());
+
+    testing_env!(context.build());
+
+    (contract, context)
+}
+
+/// Pays for the storage registration of `account_id`.
+pub fn register(contract: &mut Contract, context: &mut VMContextBuilder, account_id: AccountId) {
+    testing_env!(context
+        .predecessor_account_id(account_id.clone())
+        .attached_deposit(contract.storage_balance_bounds().min)
+        .build());
+    contract.storage_deposit(Some(account_id), None);
+}
+
+pub fn transfer(
+    contract: &mut Contract,
+    context: &mut VMContextBuilder,
+    sender_id: AccountId,
+    receiver_id: AccountId,
+    amount: Balance,
+) {
+    testing_env!(context
+        .predecessor_account_id(sender_id)
+        .attached_deposit(ONE_YOCTO)
+        .build());
+    contract.ft_transfer(receiver_id, amount.into(), None);
+}
+
+/// Calls as `account_id` at `timestamp`, attaching one yoctoNEAR.
+pub fn at(context: &mut VMContextBuilder, timestamp: Timestamp, account_id: AccountId) {
+    testing_env!(context
+        .block_timestamp(timestamp)
+        .predecessor_account_id(account_id)
+        .attached_deposit(ONE_YOCTO)
+        .build());
+}
+
+/// Freezes `account_id`, with `owner()` acting as the compliance officer.
+pub fn freeze(contract: &mut Contract, context: &mut VMContextBuilder, account_id: AccountId) {
+    testing_env!(context
+        .predecessor_account_id(owner())
+        .attached_deposit(ONE_YOCTO)
+        .build());
+    contract.compliance_add_officer(owner());
+    contract.compliance_freeze(account_id, "exploit".to_string());
+}
@@ -0,0 +1,202 @@
+//! Leaderboard of the largest holders, maintained on every balance change.
+//!
+//! The leaderboard keeps at most [`TOP_HOLDERS_CAPACITY`] accounts sorted by balance, so each
+//! update reads and writes a bounded amount of storage. The set of ranked accounts and the
+//! balance needed to enter the full leaderboard are kept apart, so the balance changes of
+//! accounts that are neither ranked nor entering, which are most of them, skip the leaderboard
+//! entirely, and it is only written when the ranking changes. It is kept without scanning all the
+//! balances, which means an account enters it only when its own balance changes: a holder that
+//! was pushed out by larger ones and later overtakes a listed account shows up again on its next
+//! transfer. Listed accounts stay listed until their balance drops to zero or a larger holder
+//! takes their place. The contract account, which holds the locked tokens, is not ranked.
+use near_contract_standards::fungible_token::Balance;
+use near_sdk::collections::{LazyOption, LookupSet};
+use near_sdk::json_types::U128;
+use near_sdk::{env, near, AccountId};
+
+use crate::{Contract, ContractExt, StorageKey};
+
+/// Maximum number of accounts on the leaderboard.
+pub const TOP_HOLDERS_CAPACITY: usize = 100;
+
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct TopHolder {
+    pub account_id: AccountId,
+    pub balance: U128,
+}
+
+#[near]
+pub struct TopHolders {
+    ranking: LazyOption<Vec<TopHolder>>,
+    /// Accounts of `ranking`.
+    ranked: LookupSet<AccountId>,
+    /// Balance to exceed to enter the leaderboard: the smallest ranked balance once it is full,
+    /// zero before.
+    cutoff: Balance,
+}
+
+impl TopHolders {
+    pub fn new() -> Self {
+        Self {
+            ranking: LazyOption::new(StorageKey::TopHolders, Some(&Vec::new())),
+            ranked: LookupSet::new(StorageKey::TopHolderAccounts),
+            cutoff: 0,
+        }
+    }
+
+    /// Moves `account_id` to its rank for `balance`, dropping it for a zero balance.
+    fn update(&mut self, account_id: &AccountId, balance: Balance) {
+        if balance <= self.cutoff && !self.ranked.contains(account_id) {
+            return;
+        }
+        let mut ranking = self.ranking.get().unwrap_or_default();
+        let previous_rank = ranking
+            .iter()
+            .position(|holder| holder.account_id == *account_id);
+        if let Some(rank) = previous_rank {
+            if ranking[rank].balance.0 == balance {
+                return;
+            }
+            ranking.remove(rank);
+        }
+        let was_listed = previous_rank.is_some();
+
+        let fits = ranking.len() < TOP_HOLDERS_CAPACITY
+            || ranking.last().is_some_and(|last| balance > last.balance.0);
+        if balance > 0 && fits {
+            let rank = ranking.partition_point(|holder| holder.balance.0 >= balance);
+            ranking.insert(
+                rank,
+                TopHolder {
+                    account_id: account_id.clone(),
+                    balance: balance.into(),
+                },
+            );
+            if !was_listed {
+                self.ranked.insert(account_id);
+            }
+            if let Some(evicted) = ranking.get(TOP_HOLDERS_CAPACITY) {
+                self.ranked.remove(&evicted.account_id);
+            }
+            ranking.truncate(TOP_HOLDERS_CAPACITY);
+        } else if was_listed {
+            self.ranked.remove(account_id);
+        } else {
+            return;
+        }
+        self.cutoff = match ranking.last() {
+            Some(last) if ranking.len() == TOP_HOLDERS_CAPACITY => last.balance.0,
+            _ => 0,
+        };
+        self.ranking.set(&ranking);
+    }
+}
+
+impl Contract {
+    pub(crate) fn internal_update_top_holders(&mut self, account_id: Option<&AccountId>) {
+        if let Some(account_id) = account_id {
+            if *account_id != env::current_account_id() {
+                let balance = self.token.accounts.get(account_id).unwrap_or(0);
+                self.top_holders.update(account_id, balance);
+            }
+        }
+    }
+}
+
+#[near]
+impl Contract {
+    /// Returns up to `limit` of the largest holders, largest first.
+    pub fn ft_top_holders(&self, limit: Option<u64>) -> Vec<TopHolder> {
+        let mut ranking = self.top_holders.ranking.get().unwrap_or_default();
+        ranking.truncate(limit.unwrap_or(u64::MAX).min(TOP_HOLDERS_CAPACITY as u64) as usize);
+        ranking
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::accounts;
+
+    use super::*;
+    use crate::test_utils::*;
+
+    fn ranked(contract: &Contract) -> Vec<(AccountId, Balance)> {
+        contract
+            .ft_top_holders(None)
+            .into_iter()
+            .map(|holder| (holder.account_id, holder.balance.0))
+            .collect()
+    }
+
+    #[test]
+    fn test_top_holders_follow_transfers() {
+        let (mut contract, mut context) = setup();
+        register(&mut contract, &mut context, user1());
+        register(&mut contract, &mut context, user2());
+
+        transfer(&mut contract, &mut context, owner(), user1(), 100);
+        transfer(&mut contract, &mut context, owner(), user2(), 200);
+        assert_eq!(
+            ranked(&contract),
+            vec![
+                (owner(), TOTAL_SUPPLY - 300),
+                (user2(), 200),
+                (user1(), 100),
+            ]
+        );
+
+        transfer(&mut contract, &mut context, user2(), user1(), 200);
+        assert_eq!(ranked(&contract)[1], (user1(), 300));
+        assert_eq!(contract.ft_top_holders(Some(1)).len(), 1);
+        assert_eq!(ranked(&contract).len(), 2);
+    }
+
+    #[test]
+    fn test_top_holders_capacity() {
+        let mut top_holders = TopHolders::new();
+        for i in 0..TOP_HOLDERS_CAPACITY as u128 {
+            let account_id = format!("holder{}.near", i).parse().unwrap();
+            top_holders.update(&account_id, 10 + i);
+        }
+
+        // below the cutoff, the leaderboard is neither read nor written
+        assert_eq!(top_holders.cutoff, 10);
+        let storage_usage = env::storage_usage();
+        top_holders.update(&accounts(0), 5);
+        assert_eq!(env::storage_usage(), storage_usage);
+        let ranking = top_holders.ranking.get().unwrap();
+        assert_eq!(ranking.len(), TOP_HOLDERS_CAPACITY);
+        assert!(ranking
+            .iter()
+            .all(|holder| holder.account_id != accounts(0)));
+
+        top_holders.update(&accounts(0), 1_000);
+        let ranking = top_holders.ranking.get().unwrap();
+        assert_eq!(ranking.len(), TOP_HOLDERS_CAPACITY);
+        assert_eq!(ranking[0].account_id, accounts(0));
+        assert_eq!(ranking.last().unwrap().balance.0, 11);
+        assert_eq!(top_holders.cutoff, 11);
+        assert!(top_holders.ranked.contains(&accounts(0)));
+        assert!(!top_holders
+            .ranked
+            .contains(&"holder0.near".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_top_holders_drop_zero_balances() {
+        let mut top_holders = TopHolders::new();
+        top_holders.update(&accounts(0), 10);
+        top_holders.update(&accounts(1), 20);
+        top_holders.update(&accounts(0), 30);
+        top_holders.update(&accounts(1), 0);
+
+        let ranking = top_holders.ranking.get().unwrap();
+        assert_eq!(ranking.len(), 1);
+        assert_eq!(
+            (ranking[0].account_id.clone(), ranking[0].balance.0),
+            (accounts(0), 30)
+        );
+        assert!(!top_holders.ranked.contains(&accounts(1)));
+    }
+}
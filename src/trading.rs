@@ -0,0 +1,218 @@
+//! Transfer mode for the phases before the token generation event.
+//!
+//! In `AllowlistOnly` mode, tokens can only move between accounts of the admin allowlist, and in
+//! `Disabled` mode they cannot move at all. The checks apply to `ft_transfer`, `ft_transfer_call`
+//! and to the tokens locked into the features of the contract, both when they are locked and when
+//! they are paid out, between the account that locked them and the recipient. Once
+//! `enable_public_trading` has been called, the mode is `Open` for good.
+use near_sdk::collections::UnorderedSet;
+use near_sdk::{env, near, require, AccountId};
+
+use crate::events::Event;
+use crate::{Contract, ContractExt, StorageKey};
+
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TransferMode {
+    Open,
+    AllowlistOnly,
+    Disabled,
+}
+
+#[near]
+pub struct Trading {
+    mode: TransferMode,
+    public_trading_enabled: bool,
+    allowlist: UnorderedSet<AccountId>,
+}
+
+impl Trading {
+    pub fn new() -> Self {
+        Self {
+            mode: TransferMode::Open,
+            public_trading_enabled: false,
+            allowlist: UnorderedSet::new(StorageKey::TransferAllowlist),
+        }
+    }
+
+    fn is_allowlisted(&self, account_id: &AccountId) -> bool {
+        *account_id == env::current_account_id() || self.allowlist.contains(account_id)
+    }
+}
+
+impl Contract {
+    pub(crate) fn assert_transfer_mode_allows(
+        &self,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+    ) {
+        match self.trading.mode {
+            TransferMode::Open => {}
+            TransferMode::AllowlistOnly => require!(
+                self.trading.is_allowlisted(sender_id) && self.trading.is_allowlisted(receiver_id),
+                "ERR_TRANSFER_NOT_ALLOWLISTED"
+            ),
+            TransferMode::Disabled => env::panic_str("ERR_TRANSFERS_DISABLED"),
+        }
+    }
+
+    fn internal_set_transfer_mode(&mut self, mode: TransferMode) {
+        self.trading.mode = mode;
+        Event::TransferModeChanged { mode }.emit();
+    }
+}
+
+#[near]
+impl Contract {
+    pub fn set_transfer_mode(&mut self, mode: TransferMode) {
+        self.assert_owner();
+        require!(
+            !self.trading.public_trading_enabled,
+            "ERR_PUBLIC_TRADING_ALREADY_ENABLED"
+        );
+        self.internal_set_transfer_mode(mode);
+    }
+
+    /// Opens transfers to everyone. This cannot be reverted.
+    pub fn enable_public_trading(&mut self) {
+        self.assert_owner();
+        require!(
+            !self.trading.public_trading_enabled,
+            "ERR_PUBLIC_TRADING_ALREADY_ENABLED"
+        );
+        self.trading.public_trading_enabled = true;
+        self.internal_set_transfer_mode(TransferMode::Open);
+    }
+
+    pub fn transfer_allowlist_add(&mut self, account_ids: Vec<AccountId>) {
+        self.assert_owner();
+        for account_id in account_ids.iter() {
+            self.trading.allowlist.insert(account_id);
+        }
+    }
+
+    pub fn transfer_allowlist_remove(&mut self, account_ids: Vec<AccountId>) {
+        self.assert_owner();
+        for account_id in account_ids.iter() {
+            self.trading.allowlist.remove(account_id);
+        }
+    }
+
+    pub fn transfer_mode(&self) -> TransferMode {
+        self.trading.mode
+    }
+
+    pub fn is_public_trading_enabled(&self) -> bool {
+        self.trading.public_trading_enabled
+    }
+
+    pub fn is_transfer_allowlisted(&self, account_id: AccountId) -> bool {
+        self.trading.is_allowlisted(&account_id)
+    }
+
+    pub fn transfer_allowlist(
+        &self,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> Vec<AccountId> {
+        self.trading
+            .allowlist
+            .iter()
+            .skip(from_index.unwrap_or(0) as usize)
+            .take(limit.unwrap_or(u64::MAX) as usize)
+            .collect()
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_contract_standards::fungible_token::FungibleTokenCore;
+    use near_sdk::json_types::U64;
+    use near_sdk::test_utils::VMContextBuilder;
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_utils::*;
+
+    fn setup_mode(mode: TransferMode) -> (Contract, VMContextBuilder) {
+        let (mut contract, mut context) = setup();
+        register(&mut contract, &mut context, user1());
+        register(&mut contract, &mut context, user2());
+
+        testing_env!(context.predecessor_account_id(owner()).build());
+        contract.transfer_allowlist_add(vec![owner(), user1()]);
+        contract.set_transfer_mode(mode);
+
+        (contract, context)
+    }
+
+    #[test]
+    fn test_allowlist_only_between_allowlisted_accounts() {
+        let (mut contract, mut context) = setup_mode(TransferMode::AllowlistOnly);
+
+        transfer(&mut contract, &mut context, owner(), user1(), 100);
+        assert_eq!(contract.ft_balance_of(user1()).0, 100);
+        assert_eq!(contract.transfer_allowlist(None, None).len(), 2);
+    }
+
+    #[should_panic(expected = "ERR_TRANSFER_NOT_ALLOWLISTED")]
+    #[test]
+    fn test_allowlist_only_panics_for_other_receiver() {
+        let (mut contract, mut context) = setup_mode(TransferMode::AllowlistOnly);
+
+        transfer(&mut contract, &mut context, owner(), user2(), 100);
+    }
+
+    #[should_panic(expected = "ERR_TRANSFER_NOT_ALLOWLISTED")]
+    #[test]
+    fn test_allowlist_only_applies_to_locks() {
+        let (mut contract, mut context) = setup_mode(TransferMode::Open);
+        transfer(&mut contract, &mut context, owner(), user2(), 100);
+
+        testing_env!(context.predecessor_account_id(owner()).build());
+        contract.set_transfer_mode(TransferMode::AllowlistOnly);
+
+        testing_env!(context
+            .predecessor_account_id(user2())
+            .attached_deposit(ONE_NEAR)
+            .build());
+        contract.escrow_create(user1(), 100.into(), owner(), U64(1));
+    }
+
+    #[should_panic(expected = "ERR_TRANSFER_NOT_ALLOWLISTED")]
+    #[test]
+    fn test_allowlist_only_applies_to_unlocks() {
+        let (mut contract, mut context) = setup_mode(TransferMode::AllowlistOnly);
+
+        testing_env!(context
+            .predecessor_account_id(owner())
+            .attached_deposit(ONE_NEAR)
+            .build());
+        contract.escrow_create(user2(), 100.into(), user1(), U64(1));
+
+        testing_env!(context.attached_deposit(ONE_YOCTO).build());
+        contract.escrow_release(0);
+    }
+
+    #[should_panic(expected = "ERR_TRANSFERS_DISABLED")]
+    #[test]
+    fn test_disabled_mode_blocks_transfers() {
+        let (mut contract, mut context) = setup_mode(TransferMode::Disabled);
+
+        transfer(&mut contract, &mut context, owner(), user1(), 100);
+    }
+
+    #[should_panic(expected = "ERR_PUBLIC_TRADING_ALREADY_ENABLED")]
+    #[test]
+    fn test_public_trading_cannot_be_reverted() {
+        let (mut contract, mut context) = setup_mode(TransferMode::Disabled);
+
+        contract.enable_public_trading();
+        assert_eq!(contract.transfer_mode(), TransferMode::Open);
+        transfer(&mut contract, &mut context, owner(), user2(), 100);
+        assert_eq!(contract.ft_balance_of(user2()).0, 100);
+
+        testing_env!(context.predecessor_account_id(owner()).build());
+        contract.set_transfer_mode(TransferMode::AllowlistOnly);
+    }
+}
@@ -0,0 +1,592 @@
+//! Delegated voting power with per-block checkpoints.
+//!
+//! A holder delegates the voting power of their whole balance to a single account, which may be
+//! the holder itself. Every balance change then moves votes between the delegates of the sender
+//! and of the receiver, and each delegate keeps a history of checkpoints so that past voting
+//! power can be looked up by block height.
+//!
+//! Past voting power is only read at the snapshot blocks of governance proposals, so the history
+//! is compacted between snapshots: a delegate keeps at most two checkpoints after the latest
+//! snapshot, the last one before the current block and the one of the current block. Balance
+//! changes, however many and however small, therefore grow the history by at most one checkpoint
+//! per delegate and proposal, and the storage of the first checkpoints is paid by the delegator
+//! on `delegate`. Past votes are exact at every snapshot block and at the block before the
+//! current one; at other blocks they may be those of an earlier block.
+use near_contract_standards::fungible_token::Balance;
+use near_sdk::collections::LookupMap;
+use near_sdk::json_types::{Base64VecU8, U128, U64};
+use near_sdk::{
+    borsh, env, near, require, AccountId, BlockHeight, CurveType, PublicKey, Timestamp,
+};
+
+use crate::events::Event;
+use crate::internal::{assert_at_least_one_yocto, refund_deposit, verify_ed25519};
+use crate::{Contract, ContractExt, StorageKey};
+
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Checkpoint {
+    pub block_height: BlockHeight,
+    pub votes: U128,
+}
+
+/// Payload an account signs off-chain to delegate through `delegate_by_sig`.
+#[near(serializers = [borsh])]
+pub struct DelegationMessage {
+    pub contract_id: AccountId,
+    pub delegator: AccountId,
+    pub delegatee: AccountId,
+    pub nonce: u64,
+    pub expiry: Timestamp,
+}
+
+#[near]
+pub struct Votes {
+    /// Holder -> account its voting power is delegated to.
+    delegates: LookupMap<AccountId, AccountId>,
+    /// (Delegate, index) -> checkpoint, ordered by block height.
+    checkpoints: LookupMap<(AccountId, u32), Checkpoint>,
+    num_checkpoints: LookupMap<AccountId, u32>,
+    /// Next nonce expected in a `delegate_by_sig` signed by the holder.
+    nonces: LookupMap<AccountId, u64>,
+    /// Keys holders of named accounts registered to sign delegations with.
+    delegation_keys: LookupMap<AccountId, PublicKey>,
+    /// Latest block whose past votes a proposal reads.
+    snapshot_block: BlockHeight,
+}
+
+impl Votes {
+    pub fn new() -> Self {
+        Self {
+            delegates: LookupMap::new(StorageKey::Delegates),
+            checkpoints: LookupMap::new(StorageKey::Checkpoints),
+            num_checkpoints: LookupMap::new(StorageKey::NumCheckpoints),
+            nonces: LookupMap::new(StorageKey::DelegationNonces),
+            delegation_keys: LookupMap::new(StorageKey::DelegationKeys),
+            snapshot_block: 0,
+        }
+    }
+
+    /// Keeps the history up to the end of `block_height` exact from now on.
+    pub fn record_snapshot(&mut self, block_height: BlockHeight) {
+        self.snapshot_block = block_height;
+    }
+
+    pub fn delegate_of(&self, account_id: &AccountId) -> Option<AccountId> {
+        self.delegates.get(account_id)
+    }
+
+    pub fn votes_of(&self, account_id: &AccountId) -> Balance {
+        match self.num_checkpoints.get(account_id) {
+            Some(count) if count > 0 => self.checkpoint(account_id, count - 1).votes.0,
+            _ => 0,
+        }
+    }
+
+    /// Returns the votes of `account_id` at the end of `block_height`.
+    pub fn past_votes_of(&self, account_id: &AccountId, block_height: BlockHeight) -> Balance {
+        let count = self.num_checkpoints.get(account_id).unwrap_or(0);
+
+        // Binary search for the last checkpoint at or before `block_height`.
+        let (mut low, mut high) = (0, count);
+        while low < high {
+            let mid = low + (high - low) / 2;
+            if self.checkpoint(account_id, mid).block_height > block_height {
+                high = mid;
+            } else {
+                low = mid + 1;
+            }
+        }
+
+        if high == 0 {
+            0
+        } else {
+            self.checkpoint(account_id, high - 1).votes.0
+        }
+    }
+
+    /// Moves `amount` votes from one delegate to another, `None` standing for minted or
+    /// burned tokens and for holders that have not delegated.
+    pub fn move_votes(
+        &mut self,
+        from: Option<&AccountId>,
+        to: Option<&AccountId>,
+        amount: Balance,
+    ) {
+        if amount == 0 || from == to {
+            return;
+        }
+        if let Some(from) = from {
+            let votes = self
+                .votes_of(from)
+                .checked_sub(amount)
+                .unwrap_or_else(|| env::panic_str("ERR_VOTES_UNDERFLOW"));
+            self.write_checkpoint(from, votes);
+        }
+        if let Some(to) = to {
+            let votes = self
+                .votes_of(to)
+                .checked_add(amount)
+                .unwrap_or_else(|| env::panic_str("ERR_VOTES_OVERFLOW"));
+            self.write_checkpoint(to, votes);
+        }
+    }
+
+    fn checkpoint(&self, account_id: &AccountId, index: u32) -> Checkpoint {
+        self.checkpoints
+            .get(&(account_id.clone(), index))
+            .unwrap_or_else(|| env::panic_str("ERR_MISSING_CHECKPOINT"))
+    }
+
+    /// Records `votes` for the current block, overwriting the checkpoint if one was already
+    /// written in this block. Once a delegate has two checkpoints after the latest snapshot, the
+    /// older one only answers blocks that are neither a snapshot nor can become one, so it is
+    /// dropped and the history does not grow.
+    fn write_checkpoint(&mut self, account_id: &AccountId, votes: Balance) {
+        let block_height = env::block_height();
+        let count = self.num_checkpoints.get(account_id).unwrap_or(0);
+        let checkpoint = Checkpoint {
+            block_height,
+            votes: votes.into(),
+        };
+
+        if count > 0 {
+            let last = self.checkpoint(account_id, count - 1);
+            if last.block_height == block_height {
+                self.checkpoints
+                    .insert(&(account_id.clone(), count - 1), &checkpoint);
+                return;
+            }
+            if count > 1
+                && self.checkpoint(account_id, count - 2).block_height > self.snapshot_block
+            {
+                self.checkpoints
+                    .insert(&(account_id.clone(), count - 2), &last);
+                self.checkpoints
+                    .insert(&(account_id.clone(), count - 1), &checkpoint);
+                return;
+            }
+        }
+        self.checkpoints
+            .insert(&(account_id.clone(), count), &checkpoint);
+        self.num_checkpoints.insert(account_id, &(count + 1));
+    }
+}
+
+impl Contract {
+    /// Moves the voting power attached to `amount` tokens going from one holder to another.
+    pub(crate) fn internal_move_delegate_votes(
+        &mut self,
+        from: Option<&AccountId>,
+        to: Option<&AccountId>,
+        amount: Balance,
+    ) {
+        let from_delegate = from.and_then(|account_id| self.votes.delegate_of(account_id));
+        let to_delegate = to.and_then(|account_id| self.votes.delegate_of(account_id));
+        self.votes
+            .move_votes(from_delegate.as_ref(), to_delegate.as_ref(), amount);
+    }
+
+    fn internal_delegate(&mut self, delegator: &AccountId, delegatee: &AccountId) {
+        require!(
+            self.token.accounts.contains_key(delegator),
+            "ERR_DELEGATOR_NOT_REGISTERED"
+        );
+
+        let from_delegate = self.votes.delegates.insert(delegator, delegatee);
+
+        Event::DelegateChanged {
+            delegator,
+            from_delegate: from_delegate.as_ref(),
+            to_delegate: delegatee,
+        }
+        .emit();
+
+        let balance = self.token.accounts.get(delegator).unwrap_or(0);
+        self.votes
+            .move_votes(from_delegate.as_ref(), Some(delegatee), balance);
+    }
+
+    /// Checks that `public_key` is allowed to sign on behalf of `delegator`, either because
+    /// `delegator` is the implicit account of that key or because the key was registered.
+    fn assert_delegation_key(&self, delegator: &AccountId, public_key: &PublicKey) {
+        require!(
+            public_key.curve_type() == CurveType::ED25519,
+            "ERR_UNSUPPORTED_KEY_TYPE"
+        );
+        let implicit_account_id: String = public_key.as_bytes()[1..]
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+
+        require!(
+            delegator.as_str() == implicit_account_id
+                || self.votes.delegation_keys.get(delegator).as_ref() == Some(public_key),
+            "ERR_KEY_NOT_AUTHORIZED_FOR_DELEGATOR"
+        );
+    }
+}
+
+#[near]
+impl Contract {
+    /// Delegates the voting power of the predecessor to `delegatee`. The attached deposit covers
+    /// the storage of the delegation, the remainder is refunded.
+    #[payable]
+    pub fn delegate(&mut self, delegatee: AccountId) {
+        assert_at_least_one_yocto();
+        let initial_storage_usage = env::storage_usage();
+        self.internal_delegate(&env::predecessor_account_id(), &delegatee);
+        refund_deposit(initial_storage_usage);
+    }
+
+    /// Delegates the voting power of `delegator` to `delegatee` with an ed25519 signature over
+    /// the borsh-serialized [`DelegationMessage`], so the holder does not need to send a
+    /// transaction. The predecessor relays the signature and pays for the storage.
+    #[payable]
+    pub fn delegate_by_sig(
+        &mut self,
+        delegator: AccountId,
+        delegatee: AccountId,
+        nonce: u64,
+        expiry: U64,
+        public_key: PublicKey,
+        signature: Base64VecU8,
+    ) {
+        assert_at_least_one_yocto();
+        let initial_storage_usage = env::storage_usage();
+
+        require!(
+            env::block_timestamp() <= expiry.0,
+            "ERR_DELEGATION_SIGNATURE_EXPIRED"
+        );
+        require!(
+            nonce == self.delegation_nonce(delegator.clone()),
+            "ERR_INVALID_DELEGATION_NONCE"
+        );
+        self.assert_delegation_key(&delegator, &public_key);
+
+        let message = borsh::to_vec(&DelegationMessage {
+            contract_id: env::current_account_id(),
+            delegator: delegator.clone(),
+            delegatee: delegatee.clone(),
+            nonce,
+            expiry: expiry.0,
+        })
+        .unwrap_or_else(|_| env::panic_str("ERR_FAILED_TO_SERIALIZE_DELEGATION"));
+        require!(
+            verify_ed25519(&public_key, &message, &signature),
+            "ERR_INVALID_DELEGATION_SIGNATURE"
+        );
+
+        self.votes.nonces.insert(&delegator, &(nonce + 1));
+        self.internal_delegate(&delegator, &delegatee);
+        refund_deposit(initial_storage_usage);
+    }
+
+    /// Registers the ed25519 key the predecessor signs delegations with, or removes it when
+    /// `public_key` is `None`. Implicit accounts can sign with their own key without this.
+    #[payable]
+    pub fn set_delegation_key(&mut self, public_key: Option<PublicKey>) {
+        assert_at_least_one_yocto();
+        let initial_storage_usage = env::storage_usage();
+        let account_id = env::predecessor_account_id();
+        match public_key {
+            Some(public_key) => {
+                require!(
+                    public_key.curve_type() == CurveType::ED25519,
+                    "ERR_UNSUPPORTED_KEY_TYPE"
+                );
+                self.votes.delegation_keys.insert(&account_id, &public_key);
+            }
+            None => {
+                self.votes.delegation_keys.remove(&account_id);
+            }
+        }
+        refund_deposit(initial_storage_usage);
+    }
+
+    pub fn delegates(&self, account_id: AccountId) -> Option<AccountId> {
+        self.votes.delegate_of(&account_id)
+    }
+
+    pub fn delegation_nonce(&self, account_id: AccountId) -> u64 {
+        self.votes.nonces.get(&account_id).unwrap_or(0)
+    }
+
+    pub fn get_votes(&self, account_id: AccountId) -> U128 {
+        self.votes.votes_of(&account_id).into()
+    }
+
+    /// Returns the votes of `account_id` at the end of a block that is already final.
+    pub fn get_past_votes(&self, account_id: AccountId, block_height: BlockHeight) -> U128 {
+        require!(
+            block_height < env::block_height(),
+            "ERR_BLOCK_NOT_YET_MINED"
+        );
+        self.votes.past_votes_of(&account_id, block_height).into()
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use ed25519_dalek::{Signer, SigningKey};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_utils::*;
+
+    fn signing_key() -> SigningKey {
+        SigningKey::from_bytes(&[7; 32])
+    }
+
+    fn near_public_key(key: &SigningKey) -> PublicKey {
+        PublicKey::from_parts(CurveType::ED25519, key.verifying_key().to_bytes().to_vec()).unwrap()
+    }
+
+    fn sign(
+        key: &SigningKey,
+        delegator: &AccountId,
+        delegatee: &AccountId,
+        nonce: u64,
+    ) -> Base64VecU8 {
+        let message = borsh::to_vec(&DelegationMessage {
+            contract_id: current(),
+            delegator: delegator.clone(),
+            delegatee: delegatee.clone(),
+            nonce,
+            expiry: u64::MAX,
+        })
+        .unwrap();
+        key.sign(&message).to_bytes().to_vec().into()
+    }
+
+    #[test]
+    fn test_delegate_tracks_transfers() {
+        let (mut contract, mut context) = setup();
+        register(&mut contract, &mut context, user1());
+
+        testing_env!(context
+            .predecessor_account_id(owner())
+            .attached_deposit(ONE_NEAR)
+            .build());
+        contract.delegate(user2());
+        assert_eq!(contract.delegates(owner()), Some(user2()));
+        assert_eq!(contract.get_votes(user2()).0, TOTAL_SUPPLY);
+
+        transfer(
+            &mut contract,
+            &mut context,
+            owner(),
+            user1(),
+            TOTAL_SUPPLY / 4,
+        );
+        assert_eq!(contract.get_votes(user2()).0, TOTAL_SUPPLY / 4 * 3);
+
+        // user1 never delegated, so its tokens carry no votes until it does
+        testing_env!(context
+            .predecessor_account_id(user1())
+            .attached_deposit(ONE_NEAR)
+            .build());
+        contract.delegate(user1());
+        assert_eq!(contract.get_votes(user1()).0, TOTAL_SUPPLY / 4);
+    }
+
+    #[test]
+    fn test_past_votes() {
+        let (mut contract, mut context) = setup();
+        register(&mut contract, &mut context, user1());
+
+        testing_env!(context
+            .block_height(10)
+            .predecessor_account_id(owner())
+            .attached_deposit(ONE_NEAR)
+            .build());
+        contract.delegate(owner());
+
+        testing_env!(context.block_height(20).build());
+        transfer(
+            &mut contract,
+            &mut context,
+            owner(),
+            user1(),
+            TOTAL_SUPPLY / 2,
+        );
+
+        testing_env!(context.block_height(30).build());
+        assert_eq!(contract.get_past_votes(owner(), 9).0, 0);
+        assert_eq!(contract.get_past_votes(owner(), 10).0, TOTAL_SUPPLY);
+        assert_eq!(contract.get_past_votes(owner(), 19).0, TOTAL_SUPPLY);
+        assert_eq!(contract.get_past_votes(owner(), 20).0, TOTAL_SUPPLY / 2);
+    }
+
+    #[test]
+    fn test_checkpoints_compact_between_snapshots() {
+        let (mut contract, mut context) = setup();
+        register(&mut contract, &mut context, user1());
+        testing_env!(context
+            .block_height(1)
+            .predecessor_account_id(owner())
+            .attached_deposit(ONE_NEAR)
+            .build());
+        contract.delegate(owner());
+
+        // dust transfers over many blocks do not grow the history
+        for block_height in 2..20 {
+            testing_env!(context.block_height(block_height).build());
+            transfer(&mut contract, &mut context, owner(), user1(), 1);
+        }
+        assert_eq!(contract.votes.num_checkpoints.get(&owner()), Some(2));
+        assert_eq!(contract.get_votes(owner()).0, TOTAL_SUPPLY - 18);
+
+        // a snapshot keeps the votes at its block
+        contract.votes.record_snapshot(19);
+        for block_height in 20..30 {
+            testing_env!(context.block_height(block_height).build());
+            transfer(&mut contract, &mut context, owner(), user1(), 1);
+        }
+        assert_eq!(contract.votes.num_checkpoints.get(&owner()), Some(4));
+        assert_eq!(contract.get_past_votes(owner(), 19).0, TOTAL_SUPPLY - 18);
+        assert_eq!(contract.get_past_votes(owner(), 28).0, TOTAL_SUPPLY - 27);
+        assert_eq!(contract.get_votes(owner()).0, TOTAL_SUPPLY - 28);
+    }
+
+    #[should_panic(expected = "ERR_BLOCK_NOT_YET_MINED")]
+    #[test]
+    fn test_past_votes_panics_on_current_block() {
+        let (contract, _) = setup();
+
+        contract.get_past_votes(owner(), env::block_height());
+    }
+
+    #[test]
+    fn test_delegate_by_sig() {
+        let (mut contract, mut context) = setup();
+        let key = signing_key();
+
+        testing_env!(context
+            .predecessor_account_id(owner())
+            .attached_deposit(ONE_NEAR)
+            .build());
+        contract.set_delegation_key(Some(near_public_key(&key)));
+
+        // a relayer submits the signed delegation
+        testing_env!(context
+            .predecessor_account_id(user1())
+            .attached_deposit(ONE_NEAR)
+            .build());
+        contract.delegate_by_sig(
+            owner(),
+            user2(),
+            0,
+            u64::MAX.into(),
+            near_public_key(&key),
+            sign(&key, &owner(), &user2(), 0),
+        );
+
+        assert_eq!(contract.delegates(owner()), Some(user2()));
+        assert_eq!(contract.get_votes(user2()).0, TOTAL_SUPPLY);
+        assert_eq!(contract.delegation_nonce(owner()), 1);
+    }
+
+    #[test]
+    fn test_delegate_by_sig_from_implicit_account() {
+        let (mut contract, mut context) = setup();
+        let key = signing_key();
+        let implicit_account_id: AccountId = hex_account_id(&key);
+        register(&mut contract, &mut context, implicit_account_id.clone());
+
+        testing_env!(context
+            .predecessor_account_id(user1())
+            .attached_deposit(ONE_NEAR)
+            .build());
+        contract.delegate_by_sig(
+            implicit_account_id.clone(),
+            user2(),
+            0,
+            u64::MAX.into(),
+            near_public_key(&key),
+            sign(&key, &implicit_account_id, &user2(), 0),
+        );
+
+        assert_eq!(contract.delegates(implicit_account_id), Some(user2()));
+    }
+
+    #[should_panic(expected = "ERR_INVALID_DELEGATION_NONCE")]
+    #[test]
+    fn test_delegate_by_sig_panics_on_replay() {
+        let (mut contract, mut context) = setup();
+        let key = signing_key();
+
+        testing_env!(context
+            .predecessor_account_id(owner())
+            .attached_deposit(ONE_NEAR)
+            .build());
+        contract.set_delegation_key(Some(near_public_key(&key)));
+
+        testing_env!(context
+            .predecessor_account_id(user1())
+            .attached_deposit(ONE_NEAR)
+            .build());
+        for _ in 0..2 {
+            contract.delegate_by_sig(
+                owner(),
+                user2(),
+                0,
+                u64::MAX.into(),
+                near_public_key(&key),
+                sign(&key, &owner(), &user2(), 0),
+            );
+        }
+    }
+
+    #[should_panic(expected = "ERR_KEY_NOT_AUTHORIZED_FOR_DELEGATOR")]
+    #[test]
+    fn test_delegate_by_sig_panics_on_unregistered_key() {
+        let (mut contract, mut context) = setup();
+        let key = signing_key();
+
+        testing_env!(context
+            .predecessor_account_id(user1())
+            .attached_deposit(ONE_NEAR)
+            .build());
+        contract.delegate_by_sig(
+            owner(),
+            user2(),
+            0,
+            u64::MAX.into(),
+            near_public_key(&key),
+            sign(&key, &owner(), &user2(), 0),
+        );
+    }
+
+    #[should_panic(expected = "ERR_DELEGATION_SIGNATURE_EXPIRED")]
+    #[test]
+    fn test_delegate_by_sig_panics_when_expired() {
+        let (mut contract, mut context) = setup();
+        let key = signing_key();
+
+        testing_env!(context
+            .block_timestamp(100)
+            .predecessor_account_id(user1())
+            .attached_deposit(ONE_NEAR)
+            .build());
+        contract.delegate_by_sig(
+            owner(),
+            user2(),
+            0,
+            99.into(),
+            near_public_key(&key),
+            sign(&key, &owner(), &user2(), 0),
+        );
+    }
+
+    fn hex_account_id(key: &SigningKey) -> AccountId {
+        key.verifying_key()
+            .to_bytes()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<String>()
+            .parse()
+            .unwrap()
+    }
+}
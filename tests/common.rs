@@ -6,6 +6,8 @@ use near_workspaces::{Account, Contract, DevNetwork, Worker};
 
 const INITIAL_BALANCE: NearToken = NearToken::from_near(30);
 pub const ONE_YOCTO: NearToken = NearToken::from_yoctonear(1);
+/// Bytes paid at registration: the balance, the holder index entry and the account statistics.
+pub const ACCOUNT_STORAGE_USAGE: u128 = 535;
 
 static FUNGIBLE_TOKEN_CONTRACT_WASM: LazyLock<Vec<u8>> = LazyLock::new(|| {
     let artifact = cargo_near_build::build(BuildOpts {
@@ -92,7 +94,7 @@ pub async fn init_contracts(
     let res = ft_contract
         .call("storage_deposit")
         .args_json((account.id(), Option::<bool>::None))
-        .deposit(near_sdk::env::storage_byte_cost().saturating_mul(125))
+        .deposit(near_sdk::env::storage_byte_cost().saturating_mul(ACCOUNT_STORAGE_USAGE))
         .max_gas()
         .transact()
         .await?;
@@ -106,7 +108,7 @@ pub async fn register_user(contract: &Contract, account_id: &AccountId) -> anyho
         .call("storage_deposit")
         .args_json((account_id, Option::<bool>::None))
         .max_gas()
-        .deposit(near_sdk::env::storage_byte_cost().saturating_mul(125))
+        .deposit(near_sdk::env::storage_byte_cost().saturating_mul(ACCOUNT_STORAGE_USAGE))
         .transact()
         .await?;
     assert!(res.is_success());
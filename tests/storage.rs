@@ -2,7 +2,7 @@ pub mod common;
 
 use near_sdk::{json_types::U128, NearToken};
 
-use common::{init_accounts, init_contracts, ONE_YOCTO};
+use common::{init_accounts, init_contracts, ACCOUNT_STORAGE_USAGE, ONE_YOCTO};
 
 #[tokio::test]
 async fn storage_deposit_not_enough_deposit() -> anyhow::Result<()> {
@@ -24,7 +24,7 @@ async fn storage_deposit_not_enough_deposit() -> anyhow::Result<()> {
     let new_account_balance_before_deposit = new_account.view_account().await?.balance;
     let contract_balance_before_deposit = ft_contract.view_account().await?.balance;
 
-    let minimal_deposit = near_sdk::env::storage_byte_cost().saturating_mul(125);
+    let minimal_deposit = near_sdk::env::storage_byte_cost().saturating_mul(ACCOUNT_STORAGE_USAGE);
     let res = new_account
         .call(ft_contract.id(), "storage_deposit")
         .args(b"{}".to_vec())
@@ -72,7 +72,7 @@ async fn storage_deposit_minimal_deposit() -> anyhow::Result<()> {
     let new_account_balance_before_deposit = new_account.view_account().await?.balance;
     let contract_balance_before_deposit = ft_contract.view_account().await?.balance;
 
-    let minimal_deposit = near_sdk::env::storage_byte_cost().saturating_mul(125);
+    let minimal_deposit = near_sdk::env::storage_byte_cost().saturating_mul(ACCOUNT_STORAGE_USAGE);
     new_account
         .call(ft_contract.id(), "storage_deposit")
         .args(b"{}".to_vec())
@@ -115,7 +115,7 @@ async fn storage_deposit_refunds_excessive_deposit() -> anyhow::Result<()> {
     let (alice, _, _, _) = init_accounts(&root).await?;
     let (ft_contract, _) = init_contracts(&worker, initial_balance, &alice).await?;
 
-    let minimal_deposit = near_sdk::env::storage_byte_cost().saturating_mul(125);
+    let minimal_deposit = near_sdk::env::storage_byte_cost().saturating_mul(ACCOUNT_STORAGE_USAGE);
 
     // Check the storage balance bounds to make sure we have the right minimal deposit
     //